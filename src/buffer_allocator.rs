@@ -1,34 +1,34 @@
-///! Buffer allocation internals v2.
-///!
-///! The [BufferAllocator] is implemented using a list of
-///! stacks, with one stack for each port type. When a
-///! new buffer is required, we first try and pop a buffer
-///! off the stack. If it none are available, we allocate
-///! a new buffer. When a buffer is released, it is
-///! pushed to the top of the corresponding stack.
-///!
-///! There is some additional bookkeeping required for the
-///! buffers. [BufferRef]s contain a `ref_count` field which
-///! tracks the number of edges that still need the buffer
-///! to be alive before it can be safely released. The
-///! `generation` field is kept around for visualization
-///! of the assigned buffers during debugging.
-///!
-///! Finally, the engine using the graph needs to know
-///! the maximum number of buffers for each port type
-///! to allocate during its prepare for playback operation.
-///! We track this by counting each time a new buffer
-///! is allocated for a type in the `counts` list.
-///!
-///! Since it is not valid for the buffer allocator to
-///! keep allocating after the `counts` field has been
-///! consumed, we require consuming `self` to retrieve it.
+//! Buffer allocation internals v2.
+//!
+//! The [BufferAllocator] is implemented using a list of
+//! stacks, with one stack for each port type. When a
+//! new buffer is required, we first try and pop a buffer
+//! off the stack. If it none are available, we allocate
+//! a new buffer. When a buffer is released, it is
+//! pushed to the top of the corresponding stack.
+//!
+//! There is some additional bookkeeping required for the
+//! buffers. [BufferRef]s contain a `ref_count` field which
+//! tracks the number of edges that still need the buffer
+//! to be alive before it can be safely released. The
+//! `generation` field is kept around for visualization
+//! of the assigned buffers during debugging.
+//!
+//! Finally, the engine using the graph needs to know
+//! the maximum number of buffers for each port type
+//! to allocate during its prepare for playback operation.
+//! We track this by counting each time a new buffer
+//! is allocated for a type in the `counts` list.
+//!
+//! Since it is not valid for the buffer allocator to
+//! keep allocating after the `counts` field has been
+//! consumed, we require consuming `self` to retrieve it.
 use std::rc::Rc;
 
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
 
-use crate::{BufferIdx, TypeIdx};
+use crate::{BufferEvent, BufferIdx, TypeIdx};
 
 /// A reference to an abstract buffer during buffer allocation.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
@@ -63,6 +63,9 @@ pub struct BufferAllocator {
     pub free_lists: Vec<Vec<FreeListEntry>>,
     /// A list of the maximum number of buffers used for each port type.
     pub counts: Vec<usize>,
+    /// Every buffer acquired and released so far, in order, or `None` if
+    /// events are not being recorded.
+    pub events: Option<Vec<BufferEvent>>,
 }
 
 /// A small helper struct for tracking the index and generation
@@ -81,9 +84,20 @@ impl BufferAllocator {
         Self {
             free_lists: vec![vec![]; num_types],
             counts: vec![0; num_types],
+            events: None,
         }
     }
 
+    /// Start recording every buffer that is acquired and released.
+    pub fn record_events(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the events recorded so far, leaving an empty list.
+    pub fn take_events(&mut self) -> Vec<BufferEvent> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
     /// Acquire a new buffer with a given type index. Panics if
     /// the type index is out of bounds.
     pub fn acquire(&mut self, type_idx: TypeIdx) -> Rc<BufferRef> {
@@ -95,12 +109,18 @@ impl BufferAllocator {
                 generation: 0,
             }
         });
+        if let Some(events) = &mut self.events {
+            events.push(BufferEvent::Acquire(entry.idx, type_idx));
+        }
         Rc::new(BufferRef::new(entry.idx, type_idx, entry.generation))
     }
 
     /// Release a BufferRef.
     pub fn release(&mut self, buffer_ref: Rc<BufferRef>) {
         if Rc::strong_count(&buffer_ref) == 1 {
+            if let Some(events) = &mut self.events {
+                events.push(BufferEvent::Release(buffer_ref.idx, buffer_ref.type_idx));
+            }
             self.free_lists[buffer_ref.type_idx.0].push(FreeListEntry {
                 idx: buffer_ref.idx,
                 generation: buffer_ref.generation + 1,
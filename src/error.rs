@@ -1,7 +1,8 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 
-use crate::{Edge, EdgeID, NodeID, Port, PortID, TypeIdx};
+use crate::{BufferIdx, Edge, EdgeID, NodeID, Port, PortID, TypeIdx};
 
 /// An error occurred while attempting to add a port to the graph.
 #[derive(Debug, Clone, Copy)]
@@ -70,6 +71,34 @@ impl fmt::Display for RemovePortError {
     }
 }
 
+/// An error occurred while attempting to find a port in the graph.
+#[derive(Debug, Clone, Copy)]
+pub enum FindPortError {
+    /// The given node was not found in the graph.
+    NodeNotFound(NodeID),
+    /// The given port was not found in this node.
+    PortNotFound(NodeID, PortID),
+}
+
+impl Error for FindPortError {}
+
+impl fmt::Display for FindPortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeNotFound(node_id) => {
+                write!(f, "Could not find node with ID {:?}", node_id)
+            }
+            Self::PortNotFound(node_id, port_id) => {
+                write!(
+                    f,
+                    "Could not find port with ID {:?} in node with ID {:?}",
+                    port_id, node_id,
+                )
+            }
+        }
+    }
+}
+
 /// An error occurred while attempting to add an edge to the graph.
 #[derive(Debug, Clone)]
 pub enum AddEdgeError {
@@ -81,6 +110,10 @@ pub enum AddEdgeError {
     SrcPortNotFound(NodeID, PortID),
     /// The given destination port was not found in the graph.
     DstPortNotFound(NodeID, PortID),
+    /// The source node has no output port at the given index.
+    SrcPortIndexOutOfBounds(NodeID, usize),
+    /// The destination node has no input port at the given index.
+    DstPortIndexOutOfBounds(NodeID, usize),
     /// The source port and the destination port have different
     /// type indexes.
     TypeMismatch {
@@ -93,6 +126,12 @@ pub enum AddEdgeError {
     EdgeAlreadyExists(Edge),
     /// This edge would have created a cycle in the graph.
     CycleDetected,
+    /// No converter is registered from the type of the source port to the
+    /// type of the destination port.
+    NoTypeConverter { from: TypeIdx, to: TypeIdx },
+    /// The edge was rejected by the validator set with
+    /// `AudioGraphHelper::set_edge_validator()`.
+    Custom(Cow<'static, str>),
 }
 
 impl Error for AddEdgeError {}
@@ -130,6 +169,21 @@ impl fmt::Display for AddEdgeError {
                     node_id,
                 )
             }
+            Self::SrcPortIndexOutOfBounds(node_id, index) => {
+                write!(
+                    f,
+                    "Could not add edge: source node with ID {:?} has no output port at index {}",
+                    node_id, index,
+                )
+            }
+            Self::DstPortIndexOutOfBounds(node_id, index) => {
+                write!(
+                    f,
+                    "Could not add edge: destination node with ID {:?} has no input port at index {}",
+                    node_id,
+                    index,
+                )
+            }
             Self::TypeMismatch {
                 src_node_id,
                 src_port,
@@ -157,13 +211,49 @@ impl fmt::Display for AddEdgeError {
             Self::CycleDetected => {
                 write!(f, "Could not add edge: cycle was detected")
             }
+            Self::NoTypeConverter { from, to } => {
+                write!(
+                    f,
+                    "Could not add edge: no converter is registered from type {:?} to type {:?}",
+                    from, to
+                )
+            }
+            Self::Custom(message) => {
+                write!(f, "Could not add edge: {}", message)
+            }
+        }
+    }
+}
+
+/// An error occurred while attempting to build a graph with
+/// `AudioGraphHelper::from_spec()`.
+#[derive(Debug, Clone)]
+pub enum FromSpecError {
+    /// One of the ports could not be added.
+    AddPort(AddPortError),
+    /// One of the edges could not be added.
+    AddEdge(AddEdgeError),
+    /// A node or port index is too large to be used as an ID.
+    IndexOutOfRange(usize),
+}
+
+impl Error for FromSpecError {}
+
+impl fmt::Display for FromSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddPort(e) => write!(f, "Could not build graph: {}", e),
+            Self::AddEdge(e) => write!(f, "Could not build graph: {}", e),
+            Self::IndexOutOfRange(index) => {
+                write!(f, "Could not build graph: index {} is too large", index)
+            }
         }
     }
 }
 
 /// An error occurred while attempting to compile the audio graph
 /// into a schedule.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum CompileGraphError {
     /// A cycle was detected in the graph.
     CycleDetected,
@@ -185,6 +275,28 @@ pub enum CompileGraphError {
         src_port_type: TypeIdx,
         dst_port_type: TypeIdx,
     },
+    /// A required input port has no incoming edges.
+    UnconnectedInput(NodeID, PortID),
+    /// A node in the graph does not appear in the compiled schedule.
+    IncompleteSchedule(NodeID),
+    /// The block size is not a multiple of the block size required by
+    /// the node.
+    IncompatibleBlockSize(NodeID),
+    /// The block size is zero.
+    ZeroBlockSize,
+    /// A node that was given as a source has incoming edges.
+    SourceHasDependencies(NodeID),
+    /// A node downstream of the given sources depends on this node, which
+    /// is not downstream of any of them.
+    UnlistedSource(NodeID),
+    /// The input data contained an edge that does not go from an output
+    /// port to an input port.
+    InvalidDirection(Edge),
+    /// The total latency of the graph is more than the allowed maximum.
+    LatencyBudgetExceeded { total: f64, max: f64 },
+    /// A custom error, for passes over the [GraphIR](crate::GraphIR) that
+    /// are not part of this crate.
+    Custom(Cow<'static, str>),
 }
 
 impl Error for CompileGraphError {}
@@ -218,6 +330,88 @@ impl fmt::Display for CompileGraphError {
             } => {
                 write!(f, "Failed to compile audio graph: input data contains an edge {:?} with an input port of type {:?} and an output port of type {:?}", edge, src_port_type, dst_port_type)
             }
+            Self::UnconnectedInput(node_id, port_id) => {
+                write!(f, "Failed to compile audio graph: required input port {:?} on node {:?} has no incoming edges", port_id, node_id)
+            }
+            Self::IncompleteSchedule(node_id) => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: node {:?} is missing from the schedule",
+                    node_id
+                )
+            }
+            Self::IncompatibleBlockSize(node_id) => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: the block size is not compatible with node {:?}",
+                    node_id
+                )
+            }
+            Self::ZeroBlockSize => {
+                write!(f, "Failed to compile audio graph: the block size is zero")
+            }
+            Self::SourceHasDependencies(node_id) => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: source node {:?} has incoming edges",
+                    node_id
+                )
+            }
+            Self::UnlistedSource(node_id) => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: node {:?} is not downstream of any of the given sources",
+                    node_id
+                )
+            }
+            Self::InvalidDirection(edge) => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: input data contains an edge {:?} that does not go from an output port to an input port",
+                    edge
+                )
+            }
+            Self::LatencyBudgetExceeded { total, max } => {
+                write!(
+                    f,
+                    "Failed to compile audio graph: the total latency {} is more than the maximum of {}",
+                    total, max
+                )
+            }
+            Self::Custom(message) => {
+                write!(f, "Failed to compile audio graph: {}", message)
+            }
+        }
+    }
+}
+
+/// An error occurred while attempting to remap the buffer indices of
+/// a compiled schedule.
+#[derive(Debug, Clone, Copy)]
+pub enum RemapBuffersError {
+    /// Two different buffers of the same type were mapped to the same
+    /// new buffer index.
+    NotInjective {
+        type_idx: TypeIdx,
+        new_buffer_index: BufferIdx,
+    },
+}
+
+impl Error for RemapBuffersError {}
+
+impl fmt::Display for RemapBuffersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInjective {
+                type_idx,
+                new_buffer_index,
+            } => {
+                write!(
+                    f,
+                    "Could not remap buffers: more than one buffer of type {:?} was mapped to index {:?}",
+                    type_idx, new_buffer_index
+                )
+            }
         }
     }
 }
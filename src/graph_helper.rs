@@ -3,15 +3,98 @@
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
 
-use fnv::FnvHashMap;
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
 
-use crate::error::{AddEdgeError, AddPortError, CompileGraphError, RemovePortError};
-use crate::{CompiledSchedule, Edge, EdgeID, GraphIR, Node, NodeID, Port, PortID, TypeIdx};
+use fnv::{FnvHashMap, FnvHashSet, FnvHasher};
+
+use crate::error::{
+    AddEdgeError, AddPortError, CompileGraphError, FindPortError, FromSpecError, RemovePortError,
+};
+use crate::{
+    BufferEvent, CompiledSchedule, Edge, EdgeID, GraphIR, Node, NodeID, OpSchedule, Port, PortID,
+    ScheduleEntry, TypeIdx,
+};
+
+/// What `AudioGraphHelper::add_edge()` and friends do when an edge between
+/// the same two ports already exists.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateEdgePolicy {
+    /// Return `AddEdgeError::EdgeAlreadyExists`. This is the default.
+    Error,
+    /// Leave the existing edge as it is and return its ID.
+    Ignore,
+    /// Remove the existing edge and add the new one in its place, with a
+    /// new ID. This can be used to change the gain or weakness of an edge.
+    Replace,
+}
+
+/// A custom rule that every new edge must pass, set with
+/// `AudioGraphHelper::set_edge_validator()`.
+///
+/// The arguments are the graph before the edge is added, and the source
+/// node, source port, destination node and destination port of the edge.
+pub type EdgeValidator =
+    dyn Fn(&AudioGraphHelper, NodeID, PortID, NodeID, PortID) -> Result<(), AddEdgeError>;
+
+/// The wiring that `AudioGraphHelper::solo_output()` replaced, which is
+/// restored by passing this to `AudioGraphHelper::unsolo()`.
+#[derive(Debug)]
+#[must_use = "the original wiring is only restored by passing this to `AudioGraphHelper::unsolo()`"]
+pub struct SoloToken {
+    /// The edge from the soloed port into the master input, or `None` if
+    /// it was removed before a compact.
+    soloed: Option<EdgeID>,
+    /// The edges into the master input that were removed, sorted by ID.
+    /// Only their ports, gain, tag and whether they are weak are restored.
+    removed: Vec<Edge>,
+    /// The removed edges that can no longer be restored.
+    stale: Vec<Edge>,
+}
+
+impl SoloToken {
+    /// Update the nodes and edges this token refers to after
+    /// `AudioGraphHelper::compact()`, using the old and new ID of every
+    /// node and edge that it returned.
+    ///
+    /// Edges to nodes that are not in `node_map` can't be restored anymore,
+    /// and are returned by `AudioGraphHelper::unsolo()` as skipped.
+    pub fn remap(&mut self, node_map: &[(NodeID, NodeID)], edge_map: &[(EdgeID, EdgeID)]) {
+        let remap = |node_id: NodeID| {
+            node_map
+                .binary_search_by_key(&node_id.0, |(old, _)| old.0)
+                .ok()
+                .map(|i| node_map[i].1)
+        };
+
+        self.soloed = self.soloed.and_then(|edge_id| {
+            edge_map
+                .binary_search_by_key(&edge_id.0, |(old, _)| old.0)
+                .ok()
+                .map(|i| edge_map[i].1)
+        });
+
+        for mut edge in std::mem::take(&mut self.removed) {
+            match (remap(edge.src_node), remap(edge.dst_node)) {
+                (Some(src_node), Some(dst_node)) => {
+                    edge.src_node = src_node;
+                    edge.dst_node = dst_node;
+                    self.removed.push(edge);
+                }
+                _ => self.stale.push(edge),
+            }
+        }
+    }
+}
 
 /// A helper struct to construct and modify audio graphs.
 pub struct AudioGraphHelper {
     nodes: FnvHashMap<NodeID, Node>,
     edges: FnvHashMap<EdgeID, Edge>,
+    /// The ID of every edge, keyed by its source and destination ports.
+    edge_keys: FnvHashMap<(NodeID, PortID, NodeID, PortID), EdgeID>,
 
     next_node_id: u32,
     next_edge_id: u32,
@@ -19,6 +102,35 @@ pub struct AudioGraphHelper {
     free_edge_ids: Vec<EdgeID>,
 
     needs_compile: bool,
+    /// Whether anything other than the latency of a node has changed
+    /// since the last compile.
+    connectivity_changed: bool,
+    /// The schedule produced by the last call to `compile()`.
+    last_schedule: Option<CompiledSchedule>,
+    /// The buffer allocator events of the last call to `compile()`, or
+    /// `None` if they are not being recorded.
+    allocation_log: Option<Vec<BufferEvent>>,
+    /// Whether the last call to `compile()` processed the nodes in a
+    /// different order than the call before it.
+    order_changed: bool,
+    /// The nodes whose bindings changed in the last call to `compile()`.
+    schedule_delta: Vec<NodeID>,
+
+    eliminate_dead_nodes: bool,
+    sort_edges: bool,
+    cycle_check_enabled: bool,
+    duplicate_edge_policy: DuplicateEdgePolicy,
+    /// The latency of the converter node for each pair of port types,
+    /// keyed by the source and destination type.
+    type_converters: FnvHashMap<(TypeIdx, TypeIdx), f64>,
+    edge_validator: Option<Box<EdgeValidator>>,
+
+    /// The edges added since `begin_batch()` was called, or `None` if
+    /// the helper is not currently in batch mode.
+    batch_edges: Option<Vec<EdgeID>>,
+    /// The edges that were replaced by the edges in `batch_edges`, to be
+    /// restored if the batch is rolled back.
+    batch_replaced: Vec<Edge>,
 
     num_port_types: usize,
 }
@@ -27,9 +139,9 @@ impl AudioGraphHelper {
     /// Construct a new [AudioGraphHelper].
     ///
     /// * `num_port_types` - The total number of port types that can
-    /// exist in this audio graph. For example, if your graph can have
-    /// an audio port type and an event port type, then this should be
-    /// `2`. Ports of different types cannot be connected together.
+    ///   exist in this audio graph. For example, if your graph can have
+    ///   an audio port type and an event port type, then this should be
+    ///   `2`. Ports of different types cannot be connected together.
     ///
     /// ## Panics
     ///
@@ -40,13 +152,85 @@ impl AudioGraphHelper {
         Self {
             nodes: FnvHashMap::default(),
             edges: FnvHashMap::default(),
+            edge_keys: FnvHashMap::default(),
             next_node_id: 0,
             next_edge_id: 0,
             free_node_ids: Vec::new(),
             free_edge_ids: Vec::new(),
             num_port_types,
             needs_compile: false,
+            connectivity_changed: true,
+            last_schedule: None,
+            allocation_log: None,
+            order_changed: true,
+            schedule_delta: Vec::new(),
+            eliminate_dead_nodes: false,
+            sort_edges: false,
+            cycle_check_enabled: true,
+            duplicate_edge_policy: DuplicateEdgePolicy::Error,
+            type_converters: FnvHashMap::default(),
+            edge_validator: None,
+            batch_edges: None,
+            batch_replaced: Vec::new(),
+        }
+    }
+
+    /// Construct a new [AudioGraphHelper] with the given nodes, ports and
+    /// edges.
+    ///
+    /// Each node is given as its latency and its ports, where each port is
+    /// its type and whether or not it is an input port. The node at index
+    /// `i` gets the ID `NodeID(i)`, and the port at index `j` of a node gets
+    /// the ID `PortID(j)`. Each edge is given as the index of its source
+    /// node, source port, destination node and destination port, and is
+    /// checked like in `AudioGraphHelper::add_edge()`, including for cycles.
+    ///
+    /// On success, this returns the graph along with the ID of each node.
+    ///
+    /// This will return `FromSpecError::IndexOutOfRange` if there are more
+    /// nodes or ports than fit in a `u32`, or if an edge uses such an index.
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if `num_port_types == 0`.
+    pub fn from_spec(
+        num_port_types: usize,
+        nodes: &[(f64, Vec<(TypeIdx, bool)>)],
+        edges: &[(usize, usize, usize, usize)],
+    ) -> Result<(Self, Vec<NodeID>), FromSpecError> {
+        let id =
+            |index: usize| u32::try_from(index).map_err(|_| FromSpecError::IndexOutOfRange(index));
+
+        if let Some(last) = nodes.len().checked_sub(1) {
+            id(last)?;
+        }
+
+        let mut graph = Self::new(num_port_types);
+
+        let mut node_ids = Vec::with_capacity(nodes.len());
+        for (latency, ports) in nodes.iter() {
+            let node_id = graph.add_node(*latency);
+            for (i, (type_idx, is_input)) in ports.iter().enumerate() {
+                graph
+                    .add_port(node_id, PortID(id(i)?), *type_idx, *is_input)
+                    .map_err(FromSpecError::AddPort)?;
+            }
+            node_ids.push(node_id);
+        }
+
+        for (src_node, src_port, dst_node, dst_port) in edges.iter() {
+            graph
+                .add_edge(
+                    NodeID(id(*src_node)?),
+                    PortID(id(*src_port)?),
+                    NodeID(id(*dst_node)?),
+                    PortID(id(*dst_port)?),
+                    true,
+                )
+                .map_err(FromSpecError::AddEdge)?;
         }
+
+        Ok((graph, node_ids))
     }
 
     /// Add a new [Node] the the audio graph.
@@ -63,15 +247,51 @@ impl AudioGraphHelper {
             inputs: vec![],
             outputs: vec![],
             latency,
+            priority: 0,
+            block_multiple: 1,
+            is_bus: false,
+            group: 0,
         };
 
         self.nodes.insert(new_id, new_node);
 
         self.needs_compile = true;
+        self.connectivity_changed = true;
 
         new_id
     }
 
+    /// Add a copy of the given [Node] to the audio graph.
+    ///
+    /// The new node has the same latency, priority, block size requirement,
+    /// bus flag, process group and ports as the original. Since port IDs
+    /// only need to be unique to their node, the new ports have the same
+    /// IDs as the original ports. Edges are not copied.
+    ///
+    /// On success, this returns the globally unique ID assigned to the new
+    /// node.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn duplicate_node(&mut self, node_id: NodeID) -> Result<NodeID, ()> {
+        let node = self.nodes.get(&node_id).ok_or(())?;
+        let (latency, priority) = (node.latency, node.priority);
+        let (block_multiple, is_bus, group) = (node.block_multiple, node.is_bus, node.group);
+        let (inputs, outputs) = (node.inputs.clone(), node.outputs.clone());
+
+        let new_id = self.add_node(latency);
+        let new_node = self.nodes.get_mut(&new_id).unwrap();
+        new_node.priority = priority;
+        new_node.block_multiple = block_multiple;
+        new_node.is_bus = is_bus;
+        new_node.group = group;
+        new_node.inputs = inputs;
+        new_node.outputs = outputs;
+
+        Ok(new_id)
+    }
+
     /// Get info about a node.
     ///
     /// This will return `None` if a node with the given ID does not
@@ -80,10 +300,56 @@ impl AudioGraphHelper {
         self.nodes.get(&node_id)
     }
 
+    /// Get the input ports of a node, in the order they were added.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn node_inputs(&self, node_id: NodeID) -> Result<&[Port], ()> {
+        self.nodes
+            .get(&node_id)
+            .map(|node| node.inputs.as_slice())
+            .ok_or(())
+    }
+
+    /// Get the output ports of a node, in the order they were added.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn node_outputs(&self, node_id: NodeID) -> Result<&[Port], ()> {
+        self.nodes
+            .get(&node_id)
+            .map(|node| node.outputs.as_slice())
+            .ok_or(())
+    }
+
+    /// Returns `true` if a node with the given ID exists in the graph.
+    ///
+    /// This is useful for filtering out stale IDs held by the host, for
+    /// example after `AudioGraphHelper::compact()`.
+    pub fn contains_node(&self, node_id: NodeID) -> bool {
+        self.nodes.contains_key(&node_id)
+    }
+
+    /// Returns `true` if the given node exists in the graph and has an
+    /// input or output port with the given ID.
+    pub fn contains_port(&self, node_id: NodeID, port_id: PortID) -> bool {
+        match self.nodes.get(&node_id) {
+            Some(node) => node
+                .inputs
+                .iter()
+                .chain(node.outputs.iter())
+                .any(|p| p.id == port_id),
+            None => false,
+        }
+    }
+
     /// Set the latency of the given [Node] in the audio graph.
     ///
     /// This will return an error if a node with the given ID does not
     /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
     pub fn set_node_latency(&mut self, node_id: NodeID, latency: f64) -> Result<(), ()> {
         let node = self.nodes.get_mut(&node_id).ok_or(())?;
 
@@ -95,6 +361,143 @@ impl AudioGraphHelper {
         Ok(())
     }
 
+    /// Set the latencies of many nodes at once.
+    ///
+    /// This will return an error if any of the given nodes does not exist
+    /// in the graph. In this case no latencies have been changed.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_node_latencies(&mut self, updates: &[(NodeID, f64)]) -> Result<(), ()> {
+        if updates
+            .iter()
+            .any(|(node_id, _)| !self.nodes.contains_key(node_id))
+        {
+            return Err(());
+        }
+
+        for (node_id, latency) in updates.iter() {
+            self.set_node_latency(*node_id, *latency).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Set the scheduling priority of the given [Node] in the audio graph.
+    ///
+    /// When more than one node is ready to be scheduled, nodes with a
+    /// higher priority are scheduled first, and nodes with equal priority
+    /// are scheduled in the order they became ready. This never breaks the
+    /// order required by the edges of the graph. The default priority is
+    /// `0`.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_node_priority(&mut self, node_id: NodeID, priority: u8) -> Result<(), ()> {
+        let node = self.nodes.get_mut(&node_id).ok_or(())?;
+
+        if node.priority != priority {
+            node.priority = priority;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set the block size the given [Node] needs to be processed with to be
+    /// a multiple of `multiple_of`. Pass `1` to remove the requirement.
+    ///
+    /// This is checked by `AudioGraphHelper::compile_with_block_size()`.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph, or if `multiple_of` is `0`.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_node_block_requirement(
+        &mut self,
+        node_id: NodeID,
+        multiple_of: u64,
+    ) -> Result<(), ()> {
+        if multiple_of == 0 {
+            return Err(());
+        }
+        let node = self.nodes.get_mut(&node_id).ok_or(())?;
+
+        if node.block_multiple != multiple_of {
+            node.block_multiple = multiple_of;
+            self.needs_compile = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set whether or not the given node is a bus, such as a mixer bus.
+    ///
+    /// All inputs of a bus of the same type share one buffer holding the
+    /// sum of everything connected to them, and its outputs of that type
+    /// share the same buffer. This saves a buffer and a copy for every bus
+    /// in the common mixer topology. Outputs that are pinned, constant or
+    /// the source of a weak edge still get their own buffer.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_node_is_bus(&mut self, node_id: NodeID, is_bus: bool) -> Result<(), ()> {
+        let node = self.nodes.get_mut(&node_id).ok_or(())?;
+
+        if node.is_bus != is_bus {
+            node.is_bus = is_bus;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set the process group of the given node. Nodes in the same group
+    /// should be processed on the same thread. See
+    /// `AudioGraphHelper::compile_grouped()`.
+    ///
+    /// All nodes start out in group `0`.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_node_group(&mut self, node_id: NodeID, group: u32) -> Result<(), ()> {
+        let node = self.nodes.get_mut(&node_id).ok_or(())?;
+
+        if node.group != group {
+            node.group = group;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// List every edge connected to the given node, sorted by ID.
+    ///
+    /// These are the edges that `AudioGraphHelper::remove_node()` would
+    /// remove, so the host can warn the user before removing the node.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn edges_touching_node(&self, node_id: NodeID) -> Result<Vec<Edge>, ()> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(());
+        }
+
+        let mut edges = self
+            .edges
+            .values()
+            .filter(|edge| edge.src_node == node_id || edge.dst_node == node_id)
+            .copied()
+            .collect::<Vec<_>>();
+        edges.sort_unstable_by_key(|edge| edge.id.0);
+
+        Ok(edges)
+    }
+
     /// Remove the given node from the graph.
     ///
     /// This will automatically remove all edges from the graph that
@@ -105,18 +508,143 @@ impl AudioGraphHelper {
     ///
     /// This will return an error if a node with the given ID does not
     /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
     pub fn remove_node(&mut self, node_id: NodeID) -> Result<Vec<EdgeID>, ()> {
-        let node = self.nodes.remove(&node_id).ok_or(())?;
+        let mut removed_edges: Vec<Edge> = Vec::new();
+        self.remove_node_tracked(node_id, &mut removed_edges)?;
+
+        Ok(removed_edges.iter().map(|e| e.id).collect())
+    }
 
-        let mut removed_edges: Vec<EdgeID> = Vec::new();
+    /// Remove the given node from the graph, and push every edge that was
+    /// removed as a result onto `removed`.
+    ///
+    /// This is the same as `AudioGraphHelper::remove_node()`, except the
+    /// removed edges are kept whole, so the caller can tell which
+    /// connections were lost (for example, to restore them on undo).
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph. In this case `removed` is not modified.
+    #[allow(clippy::result_unit_err)]
+    pub fn remove_node_tracked(
+        &mut self,
+        node_id: NodeID,
+        removed: &mut Vec<Edge>,
+    ) -> Result<(), ()> {
+        let node = self.nodes.remove(&node_id).ok_or(())?;
 
         for port in node.inputs.iter().chain(node.outputs.iter()) {
-            removed_edges.append(&mut self.remove_edges_with_port(node_id, port.id));
+            self.remove_edges_with_port(node_id, port.id, removed);
+        }
+
+        self.needs_compile = true;
+        self.connectivity_changed = true;
+
+        Ok(())
+    }
+
+    /// The number of node IDs and the number of edge IDs that have been
+    /// handed out but are no longer in use.
+    ///
+    /// Every ID in use is below the next ID to be handed out, so this is
+    /// the difference between the two. The free ID lists are never filled,
+    /// so IDs are never reused and these only grow as nodes and edges are
+    /// removed, until `AudioGraphHelper::compact()` is called. Compared with
+    /// the number of nodes and edges in the graph, this lets the host decide
+    /// when it is worth compacting.
+    pub fn fragmentation(&self) -> (usize, usize) {
+        (
+            self.next_node_id as usize - self.nodes.len(),
+            self.next_edge_id as usize - self.edges.len(),
+        )
+    }
+
+    /// Renumber the nodes and edges of the graph so that their IDs are
+    /// dense, starting from zero, and release any unused storage.
+    ///
+    /// Node and edge IDs are never reused, so a graph that has been edited
+    /// for a long time can have IDs much larger than its size. The relative
+    /// order of IDs is kept, and port IDs are not changed.
+    ///
+    /// This returns the old and new ID of every node, and the old and new
+    /// ID of every edge, sorted by the old ID. The host must use these to
+    /// update any IDs it holds, including those in a [SoloToken] (see
+    /// `SoloToken::remap()`). If any ID changed, any previously compiled
+    /// schedule is out of date and the graph needs to be compiled again.
+    pub fn compact(&mut self) -> (Vec<(NodeID, NodeID)>, Vec<(EdgeID, EdgeID)>) {
+        let mut node_ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        node_ids.sort_unstable_by_key(|node_id| node_id.0);
+        let node_map = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, old)| (*old, NodeID(i as u32)))
+            .collect::<Vec<_>>();
+
+        let mut edge_ids = self.edges.keys().copied().collect::<Vec<_>>();
+        edge_ids.sort_unstable_by_key(|edge_id| edge_id.0);
+        let edge_map = edge_ids
+            .iter()
+            .enumerate()
+            .map(|(i, old)| (*old, EdgeID(i as u32)))
+            .collect::<Vec<_>>();
+
+        self.next_node_id = node_map.len() as u32;
+        self.next_edge_id = edge_map.len() as u32;
+        self.free_node_ids = Vec::new();
+        self.free_edge_ids = Vec::new();
+
+        let changed = node_map.iter().any(|(old, new)| old != new)
+            || edge_map.iter().any(|(old, new)| old != new);
+        if !changed {
+            self.nodes.shrink_to_fit();
+            self.edges.shrink_to_fit();
+            self.edge_keys.shrink_to_fit();
+            return (node_map, edge_map);
+        }
+
+        let new_node_ids: FnvHashMap<NodeID, NodeID> = node_map.iter().copied().collect();
+        let new_edge_ids: FnvHashMap<EdgeID, EdgeID> = edge_map.iter().copied().collect();
+
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.nodes = old_nodes
+            .into_values()
+            .map(|mut node| {
+                node.id = new_node_ids[&node.id];
+                (node.id, node)
+            })
+            .collect();
+
+        let old_edges = std::mem::take(&mut self.edges);
+        self.edges = old_edges
+            .into_values()
+            .map(|mut edge| {
+                edge.id = new_edge_ids[&edge.id];
+                edge.src_node = new_node_ids[&edge.src_node];
+                edge.dst_node = new_node_ids[&edge.dst_node];
+                (edge.id, edge)
+            })
+            .collect();
+
+        self.edge_keys = self
+            .edges
+            .values()
+            .map(|e| ((e.src_node, e.src_port, e.dst_node, e.dst_port), e.id))
+            .collect();
+
+        if let Some(batch_edges) = &mut self.batch_edges {
+            *batch_edges = batch_edges
+                .iter()
+                .filter_map(|edge_id| new_edge_ids.get(edge_id).copied())
+                .collect();
         }
+        // The IDs of the replaced edges may now be in use.
+        self.batch_replaced.clear();
 
+        self.last_schedule = None;
         self.needs_compile = true;
+        self.connectivity_changed = true;
 
-        Ok(removed_edges)
+        (node_map, edge_map)
     }
 
     /// Get a list of all the existing nodes in the graph.
@@ -124,85 +652,505 @@ impl AudioGraphHelper {
         self.nodes.values()
     }
 
+    /// Get a list of all the existing ports in the graph, along with the
+    /// ID of the node they belong to and whether or not they are an input
+    /// port.
+    ///
+    /// The input ports of each node come before its output ports.
+    pub fn ports<'a>(&'a self) -> impl Iterator<Item = (NodeID, &'a Port, bool)> + 'a {
+        self.nodes.values().flat_map(|node| {
+            node.inputs
+                .iter()
+                .map(move |p| (node.id, p, true))
+                .chain(node.outputs.iter().map(move |p| (node.id, p, false)))
+        })
+    }
+
+    /// Get a list of every node in the graph with a nonzero latency,
+    /// along with its latency, sorted by node ID.
+    pub fn nodes_with_latency(&self) -> Vec<(NodeID, f64)> {
+        let mut nodes: Vec<(NodeID, f64)> = self
+            .nodes
+            .values()
+            .filter(|n| n.latency != 0.0)
+            .map(|n| (n.id, n.latency))
+            .collect();
+        nodes.sort_unstable_by_key(|(id, _)| id.0);
+        nodes
+    }
+
     /// Get a list of all the existing edges in the graph.
     pub fn edges<'a>(&'a self) -> impl Iterator<Item = &'a Edge> + 'a {
         self.edges.values()
     }
 
-    /// Add a new [Port] to the graph.
+    /// The number of edges in the graph, including weak edges.
     ///
-    /// * `node_id` - The ID of the [Node] this port will be added to.
-    /// * `port_id` - The identifier for this port. This does not need
-    /// to be a globally unique identifier, just unique to the [Node]
-    /// it belongs to.
-    /// * `type_idx` - The type index of this port. This must be less
-    /// than the value of `num_port_types` set in the constructor
-    /// of this helper struct. Ports of different types cannot be
-    /// connected to eachother.
-    /// * `is_input` - `true` if this is an input port, `false` if this
-    /// is an output port.
+    /// Each edge is stored once, so an edge is counted once even though it
+    /// is connected to two nodes.
+    pub fn num_edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Get info about an edge.
     ///
-    /// If this returns an error, then the audio graph has not been
-    /// modified.
-    pub fn add_port(
-        &mut self,
-        node_id: NodeID,
-        port_id: PortID,
-        type_idx: TypeIdx,
-        is_input: bool,
-    ) -> Result<(), AddPortError> {
-        if type_idx.0 >= self.num_port_types {
-            return Err(AddPortError::TypeIndexOutOfBounds(
-                type_idx,
-                self.num_port_types,
-            ));
+    /// This will return `None` if an edge with the given ID does not
+    /// exist in the graph.
+    pub fn edge(&self, edge_id: EdgeID) -> Option<&Edge> {
+        self.edges.get(&edge_id)
+    }
+
+    /// Get the type index of the ports connected by the given edge.
+    ///
+    /// This will return `None` if an edge with the given ID does not
+    /// exist in the graph.
+    pub fn edge_type(&self, edge_id: EdgeID) -> Option<TypeIdx> {
+        let edge = self.edges.get(&edge_id)?;
+
+        self.nodes[&edge.src_node]
+            .outputs
+            .iter()
+            .find(|p| p.id == edge.src_port)
+            .map(|p| p.type_idx)
+    }
+
+    /// The largest number of edges connected to any single output port in
+    /// the graph, or `0` if the graph has no edges.
+    pub fn max_fan_out(&self) -> usize {
+        let mut fan_out: FnvHashMap<(NodeID, PortID), usize> = FnvHashMap::default();
+        for edge in self.edges.values() {
+            *fan_out.entry((edge.src_node, edge.src_port)).or_insert(0) += 1;
         }
+        fan_out.values().copied().max().unwrap_or(0)
+    }
 
-        let node = self
-            .nodes
-            .get_mut(&node_id)
-            .ok_or(AddPortError::NodeNotFound(node_id))?;
+    /// Find the node with the most incoming edges, including weak edges,
+    /// and the number of edges. This is usually the heaviest summing point
+    /// in the graph, and a good candidate for a bus (see
+    /// `AudioGraphHelper::set_node_is_bus()`).
+    ///
+    /// If more than one node has the most incoming edges, the one with the
+    /// lowest ID is returned. This returns `None` if the graph has no edges.
+    pub fn max_fan_in_node(&self) -> Option<(NodeID, usize)> {
+        let mut fan_in: FnvHashMap<NodeID, usize> = FnvHashMap::default();
+        for edge in self.edges.values() {
+            *fan_in.entry(edge.dst_node).or_insert(0) += 1;
+        }
+        fan_in
+            .into_iter()
+            .max_by_key(|(node_id, count)| (*count, Reverse(node_id.0)))
+    }
 
-        let new_port = Port {
-            id: port_id,
-            type_idx,
-        };
+    /// Returns `true` if every node in the graph has at most one outgoing
+    /// edge, including weak edges, so that no node has more than one
+    /// consumer.
+    pub fn is_forest(&self) -> bool {
+        let mut out_degree: FnvHashMap<NodeID, usize> = FnvHashMap::default();
+        self.edges.values().all(|edge| {
+            let degree = out_degree.entry(edge.src_node).or_insert(0);
+            *degree += 1;
+            *degree <= 1
+        })
+    }
 
-        for p in node.inputs.iter().chain(node.outputs.iter()) {
-            if p.id == port_id {
-                return Err(AddPortError::PortAlreadyExists(node_id, port_id));
+    /// Returns `true` if the graph is a single tree with `root` as its
+    /// only sink: the graph is a forest (see `AudioGraphHelper::is_forest()`),
+    /// `root` has no outgoing edges, and every node feeds into `root`.
+    ///
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn is_tree_rooted_at(&self, root: NodeID) -> Result<bool, ()> {
+        if !self.nodes.contains_key(&root) {
+            return Err(());
+        }
+        if !self.is_forest() || self.edges.values().any(|e| e.src_node == root) {
+            return Ok(false);
+        }
+
+        // Since every node has at most one consumer, a node feeds into the
+        // root only if walking its edges backwards from the root reaches it.
+        let mut reached: FnvHashSet<NodeID> = FnvHashSet::default();
+        reached.insert(root);
+        let mut stack = vec![root];
+        while let Some(node_id) = stack.pop() {
+            for edge in self.edges.values().filter(|e| e.dst_node == node_id) {
+                if reached.insert(edge.src_node) {
+                    stack.push(edge.src_node);
+                }
             }
         }
 
-        if is_input {
-            node.inputs.push(new_port);
-        } else {
-            node.outputs.push(new_port);
+        Ok(reached.len() == self.nodes.len())
+    }
+
+    /// Find every input port that is fed by more than one output port of
+    /// the same source node, which is usually a wiring mistake.
+    ///
+    /// These edges are summed into the input like any others, so this is
+    /// only a diagnostic. The ports are returned as `(node, port)` pairs,
+    /// sorted by node ID and then by port ID.
+    pub fn suspicious_summing(&self) -> Vec<(NodeID, PortID)> {
+        let mut sources: FnvHashMap<(NodeID, PortID, NodeID), usize> = FnvHashMap::default();
+        for edge in self.edges.values() {
+            *sources
+                .entry((edge.dst_node, edge.dst_port, edge.src_node))
+                .or_insert(0) += 1;
         }
 
-        self.needs_compile = true;
+        let mut ports: Vec<(NodeID, PortID)> = sources
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|((dst_node, dst_port, _), _)| (*dst_node, *dst_port))
+            .collect();
+        ports.sort_unstable_by_key(|(node_id, port_id)| (node_id.0, port_id.0));
+        ports.dedup();
+        ports
+    }
 
-        Ok(())
+    /// Find the shortest directed path of nodes from `from` to `to`, along
+    /// edges and weak edges.
+    ///
+    /// The returned path includes both `from` and `to`. This returns
+    /// `Ok(None)` if `to` is not reachable from `from`.
+    ///
+    /// This will return an error if either node does not exist in the
+    /// graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn shortest_path(&self, from: NodeID, to: NodeID) -> Result<Option<Vec<NodeID>>, ()> {
+        if !self.nodes.contains_key(&from) || !self.nodes.contains_key(&to) {
+            return Err(());
+        }
+
+        Ok(self.graph_ir().shortest_path(from, to))
     }
 
-    /// Remove the given port from the graph.
+    /// List the nodes that the given node depends on, the source nodes of
+    /// its incoming edges, sorted by ID. Each node is listed once, even if
+    /// it is connected by more than one edge. Weak edges are not included,
+    /// since they do not constrain the schedule.
     ///
-    /// This will automatically remove all edges from the graph that
-    /// were connected to this port.
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn unique_dependencies(&self, node_id: NodeID) -> Result<Vec<NodeID>, ()> {
+        self.unique_neighbors(node_id, |edge| {
+            (edge.dst_node == node_id).then_some(edge.src_node)
+        })
+    }
+
+    /// List the nodes that depend on the given node, the destination nodes
+    /// of its outgoing edges, sorted by ID. Each node is listed once, even
+    /// if it is connected by more than one edge. Weak edges are not
+    /// included, since they do not constrain the schedule.
     ///
-    /// * `node_id` - The ID of the node which the port belongs to.
-    /// * `port_id` - The ID of the port to remove.
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn unique_dependents(&self, node_id: NodeID) -> Result<Vec<NodeID>, ()> {
+        self.unique_neighbors(node_id, |edge| {
+            (edge.src_node == node_id).then_some(edge.dst_node)
+        })
+    }
+
+    /// List every node downstream of the given node, the nodes that data
+    /// from it can reach along edges, including weak edges.
     ///
-    /// On success, this returns a list of all edges that were removed
-    /// from the graph as a result of removing this node.
+    /// These are the nodes whose output may change when the output of the
+    /// given node changes. The node itself is only included if it feeds
+    /// itself through a weak edge.
     ///
-    /// If this returns an error, then the audio graph has not been
+    /// This will return an error if a node with the given ID does not
+    /// exist in the graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn descendants(&self, node_id: NodeID) -> Result<FnvHashSet<NodeID>, ()> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(());
+        }
+
+        Ok(self.graph_ir().descendants(node_id))
+    }
+
+    /// Find the smallest set of nodes that must be processed to produce the
+    /// given output ports, the nodes they belong to and every node those
+    /// depend on, including through weak edges.
+    ///
+    /// This is useful for only rendering the outputs the host is currently
+    /// monitoring. See `AudioGraphHelper::compile_up_to()` to compile a
+    /// schedule for a single node.
+    ///
+    /// This will return an error if one of the nodes does not exist, or if
+    /// one of the ports is not an output port of its node.
+    pub fn required_nodes_for(
+        &self,
+        outputs: &[(NodeID, PortID)],
+    ) -> Result<FnvHashSet<NodeID>, FindPortError> {
+        for (node_id, port_id) in outputs.iter() {
+            let node = self
+                .nodes
+                .get(node_id)
+                .ok_or(FindPortError::NodeNotFound(*node_id))?;
+            if !node.outputs.iter().any(|p| p.id == *port_id) {
+                return Err(FindPortError::PortNotFound(*node_id, *port_id));
+            }
+        }
+
+        let nodes = outputs
+            .iter()
+            .map(|(node_id, _)| *node_id)
+            .collect::<Vec<_>>();
+        Ok(self.graph_ir().required_nodes_for(&nodes))
+    }
+
+    /// Find every route that data can take from the node `from` to the node
+    /// `to` along edges, including weak edges, as the list of nodes along
+    /// it. Only routes with at most `max_depth` edges are included, which
+    /// keeps the number of routes bounded in dense graphs.
+    ///
+    /// See `GraphIR::all_paths()` for the order of the routes.
+    ///
+    /// This will return an error if either node does not exist in the
+    /// graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn all_paths(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        max_depth: usize,
+    ) -> Result<Vec<Vec<NodeID>>, ()> {
+        if !self.nodes.contains_key(&from) || !self.nodes.contains_key(&to) {
+            return Err(());
+        }
+
+        Ok(self.graph_ir().all_paths(from, to, max_depth))
+    }
+
+    /// Returns `true` if data from the node `source` can reach the node
+    /// `target` along edges, including weak edges. For example, this can
+    /// be used to check whether muting a source will silence an output. A
+    /// node always affects itself.
+    ///
+    /// This will return an error if either node does not exist in the
+    /// graph.
+    #[allow(clippy::result_unit_err)]
+    pub fn affects(&self, source: NodeID, target: NodeID) -> Result<bool, ()> {
+        if !self.nodes.contains_key(&source) || !self.nodes.contains_key(&target) {
+            return Err(());
+        }
+
+        Ok(self.graph_ir().affects(source, target))
+    }
+
+    /// Find the critical path through the graph, the chain of nodes
+    /// from a root to a sink with the largest total latency.
+    ///
+    /// Returns the total latency of the path and the nodes along it in
+    /// order. The path is empty if the graph has no nodes.
+    ///
+    /// This will return an error if the graph contains a cycle.
+    pub fn critical_path(&self) -> Result<(f64, Vec<NodeID>), CompileGraphError> {
+        self.graph_ir().critical_path()
+    }
+
+    /// Add a new [Edge] to the graph like `AudioGraphHelper::add_edge()`,
+    /// and return its ID along with the new total latency of the graph, the
+    /// latency of `AudioGraphHelper::critical_path()`.
+    ///
+    /// The critical path only exists in a graph without cycles, so the
+    /// edge is checked for cycles with `AudioGraphHelper::check_edge()`
+    /// first, even in batch mode or when cycle checks are disabled with
+    /// `AudioGraphHelper::set_cycle_check_enabled()`. This also returns
+    /// `AddEdgeError::CycleDetected` if the graph already contains a cycle
+    /// through edges that were added without checking.
+    ///
+    /// If this returns an error, then the audio graph has not been
+    /// modified.
+    pub fn add_edge_and_latency(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+    ) -> Result<(EdgeID, f64), AddEdgeError> {
+        self.check_edge(src_node_id, src_port_id, dst_node_id, dst_port_id)?;
+        let edge_id = self.add_edge(src_node_id, src_port_id, dst_node_id, dst_port_id, true)?;
+        // `check_edge()` skips the cycle check for a duplicate edge that is
+        // ignored, in which case nothing was added.
+        let (latency, _) = self
+            .critical_path()
+            .map_err(|_| AddEdgeError::CycleDetected)?;
+
+        Ok((edge_id, latency))
+    }
+
+    /// Check that the source and destination ports of every edge in the
+    /// graph have the same type.
+    ///
+    /// `AudioGraphHelper::add_edge()` already rejects mismatched edges, so
+    /// this is a safety check for edges that were constructed some other
+    /// way. This returns `CompileGraphError::EdgeTypeMismatch` for the
+    /// mismatched edge with the lowest ID.
+    pub fn validate_edge_types(&self) -> Result<(), CompileGraphError> {
+        self.graph_ir().validate_edge_types().map(|_| ())
+    }
+
+    /// Check that every edge in the graph goes from an output port to an
+    /// input port.
+    ///
+    /// `AudioGraphHelper::add_edge()` already rejects edges in the wrong
+    /// direction, so this is a safety check for edges that were constructed
+    /// some other way. This returns `CompileGraphError::InvalidDirection`
+    /// for the offending edge with the lowest ID.
+    pub fn validate_edge_directions(&self) -> Result<(), CompileGraphError> {
+        self.graph_ir().validate_edge_directions().map(|_| ())
+    }
+
+    /// Find the largest total latency of the nodes along a path from `from`
+    /// to `to`, not including `from` itself. This is how much later data
+    /// from the output of `from` arrives at the output of `to`, which is
+    /// useful for lining up a sidechain or a parallel path.
+    ///
+    /// Weak edges are not followed. This returns `Some(0.0)` if both nodes
+    /// are the same, `None` if there is no path between them or either node
+    /// does not exist, and an error if the graph contains a cycle.
+    pub fn path_latency(&self, from: NodeID, to: NodeID) -> Result<Option<f64>, CompileGraphError> {
+        self.graph_ir().path_latency(from, to)
+    }
+
+    /// Find the incoming edge of the given node whose source has the
+    /// largest total latency, which is the input that all of the node's
+    /// other inputs are delayed to line up with.
+    ///
+    /// If more than one edge ties, the one with the lowest ID is returned.
+    /// This returns `None` if the node has no incoming edges or does not
+    /// exist, and an error if the graph contains a cycle.
+    pub fn critical_input(&self, node_id: NodeID) -> Result<Option<Edge>, CompileGraphError> {
+        self.graph_ir().critical_input(node_id)
+    }
+
+    /// Group the nodes of the graph by their depth, which is the number of
+    /// edges along the longest path to the node from a node with no
+    /// incoming edges. This is useful for drawing the graph in layers.
+    ///
+    /// The nodes at depth `d` are at index `d`, sorted by node ID. Weak
+    /// edges are ignored. This will return an error if the graph contains
+    /// a cycle.
+    pub fn nodes_by_depth(&self) -> Result<Vec<Vec<NodeID>>, CompileGraphError> {
+        self.graph_ir().nodes_by_depth()
+    }
+
+    /// Find every maximal chain of two or more nodes that can be processed
+    /// as a single unit.
+    ///
+    /// Two consecutive nodes belong to the same chain when the first has
+    /// exactly one outgoing edge and the second has exactly one incoming
+    /// edge, which is that same edge. The chains are sorted by the ID of
+    /// their first node.
+    pub fn fusable_chains(&self) -> Vec<Vec<NodeID>> {
+        self.graph_ir().fusable_chains()
+    }
+
+    /// Add a new [Port] to the graph.
+    ///
+    /// * `node_id` - The ID of the [Node] this port will be added to.
+    /// * `port_id` - The identifier for this port. This does not need
+    ///   to be a globally unique identifier, just unique to the [Node]
+    ///   it belongs to.
+    /// * `type_idx` - The type index of this port. This must be less
+    ///   than the value of `num_port_types` set in the constructor
+    ///   of this helper struct. Ports of different types cannot be
+    ///   connected to eachother.
+    /// * `is_input` - `true` if this is an input port, `false` if this
+    ///   is an output port.
+    ///
+    /// If this returns an error, then the audio graph has not been
+    /// modified.
+    pub fn add_port(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        type_idx: TypeIdx,
+        is_input: bool,
+    ) -> Result<(), AddPortError> {
+        if type_idx.0 >= self.num_port_types {
+            return Err(AddPortError::TypeIndexOutOfBounds(
+                type_idx,
+                self.num_port_types,
+            ));
+        }
+
+        let node = self
+            .nodes
+            .get_mut(&node_id)
+            .ok_or(AddPortError::NodeNotFound(node_id))?;
+
+        let new_port = Port {
+            id: port_id,
+            type_idx,
+            pinned: false,
+            required: false,
+            constant: false,
+            sidechain: false,
+            clear_before_write: false,
+        };
+
+        for p in node.inputs.iter().chain(node.outputs.iter()) {
+            if p.id == port_id {
+                return Err(AddPortError::PortAlreadyExists(node_id, port_id));
+            }
+        }
+
+        if is_input {
+            node.inputs.push(new_port);
+        } else {
+            node.outputs.push(new_port);
+        }
+
+        self.needs_compile = true;
+        self.connectivity_changed = true;
+
+        Ok(())
+    }
+
+    /// Remove the given port from the graph.
+    ///
+    /// This will automatically remove all edges from the graph that
+    /// were connected to this port.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port to remove.
+    ///
+    /// On success, this returns a list of all edges that were removed
+    /// from the graph as a result of removing this node.
+    ///
+    /// If this returns an error, then the audio graph has not been
     /// modified.
     pub fn remove_port(
         &mut self,
         node_id: NodeID,
         port_id: PortID,
     ) -> Result<Vec<EdgeID>, RemovePortError> {
+        let mut removed_edges: Vec<Edge> = Vec::new();
+        self.remove_port_tracked(node_id, port_id, &mut removed_edges)?;
+
+        Ok(removed_edges.iter().map(|e| e.id).collect())
+    }
+
+    /// Remove the given port from the graph, and push every edge that was
+    /// removed as a result onto `removed`.
+    ///
+    /// This is the same as `AudioGraphHelper::remove_port()`, except the
+    /// removed edges are kept whole, so the caller can tell which
+    /// connections were lost.
+    ///
+    /// If this returns an error, then the audio graph and `removed` have
+    /// not been modified.
+    pub fn remove_port_tracked(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        removed: &mut Vec<Edge>,
+    ) -> Result<(), RemovePortError> {
         let node = self
             .nodes
             .get_mut(&node_id)
@@ -233,38 +1181,584 @@ impl AudioGraphHelper {
         }
 
         self.needs_compile = true;
+        self.connectivity_changed = true;
+
+        self.remove_edges_with_port(node_id, port_id, removed);
+
+        Ok(())
+    }
+
+    /// Set whether or not the given port is pinned.
+    ///
+    /// The buffer assigned to a pinned output port is kept alive for the
+    /// rest of the schedule, even if nothing is connected to it. This is
+    /// useful for ports the engine wants to read from directly, such as
+    /// meters and recording taps.
+    ///
+    /// This has no effect on input ports.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port.
+    /// * `pinned` - Whether or not the port should be pinned.
+    pub fn set_port_pinned(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        pinned: bool,
+    ) -> Result<(), FindPortError> {
+        let port = self.port_mut(node_id, port_id)?;
+
+        if port.pinned != pinned {
+            port.pinned = pinned;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set whether or not the buffer of the given output port must be
+    /// cleared before its node is processed.
+    ///
+    /// This is for nodes that only write to some of their output, for
+    /// example only when a voice is playing. Buffers are reused, so the
+    /// rest of the buffer would otherwise hold data from another port. The
+    /// flag is surfaced as `should_clear` on the output buffers of the
+    /// node in the schedule, and the engine should clear those buffers
+    /// before processing the node. Constant ports are never cleared.
+    ///
+    /// This has no effect on input ports.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port.
+    /// * `clear` - Whether or not the port should be cleared before the
+    ///   node writes to it.
+    pub fn set_port_clear_before_write(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        clear: bool,
+    ) -> Result<(), FindPortError> {
+        let port = self.port_mut(node_id, port_id)?;
+
+        if port.clear_before_write != clear {
+            port.clear_before_write = clear;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set whether or not the given port is a sidechain input.
+    ///
+    /// Edges into a sidechain input are never delay compensated, and the
+    /// latency of the signal arriving at it does not delay the other inputs
+    /// of the node. This keeps a sidechain from adding latency to the main
+    /// signal path.
+    ///
+    /// This has no effect on output ports.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port.
+    /// * `sidechain` - Whether or not the port is a sidechain input.
+    pub fn set_port_sidechain(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        sidechain: bool,
+    ) -> Result<(), FindPortError> {
+        let port = self.port_mut(node_id, port_id)?;
+
+        if port.sidechain != sidechain {
+            port.sidechain = sidechain;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set whether or not the given port is a constant source.
+    ///
+    /// A constant output port is assigned a buffer that is not shared with
+    /// any other port for the whole schedule. The engine fills it once
+    /// before processing starts (see `CompiledSchedule::constants`), so
+    /// constant values can be fed into the graph without a node that
+    /// writes them every cycle.
+    ///
+    /// This has no effect on input ports.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port.
+    /// * `constant` - Whether or not the port is a constant source.
+    pub fn set_port_constant(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        constant: bool,
+    ) -> Result<(), FindPortError> {
+        let port = self.port_mut(node_id, port_id)?;
 
-        Ok(self.remove_edges_with_port(node_id, port_id))
+        if port.constant != constant {
+            port.constant = constant;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Set whether or not the given port is required.
+    ///
+    /// A required input port must have at least one incoming edge when
+    /// compiling with `AudioGraphHelper::compile_strict()`.
+    ///
+    /// This has no effect on output ports.
+    ///
+    /// * `node_id` - The ID of the node which the port belongs to.
+    /// * `port_id` - The ID of the port.
+    /// * `required` - Whether or not the port is required.
+    pub fn set_port_required(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        required: bool,
+    ) -> Result<(), FindPortError> {
+        let port = self.port_mut(node_id, port_id)?;
+
+        if port.required != required {
+            port.required = required;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+
+        Ok(())
     }
 
     /// Add an [Edge] (port connection) to the graph.
     ///
     /// * `src_node_id` - The ID of the source node.
     /// * `src_port_id` - The ID of the source port. This must be an output
-    /// port on the source node.
+    ///   port on the source node.
     /// * `dst_node_id` - The ID of the destination node.
     /// * `dst_port_id` - The ID of the destination port. This must be an
-    /// input port on the destination node.
+    ///   input port on the destination node.
     /// * `check_for_cycles` - If `true`, then this will run a check to
-    /// see if adding this edge will create a cycle in the graph, and
-    /// return an error if it does.
+    ///   see if adding this edge will create a cycle in the graph, and
+    ///   return an error if it does.
     ///     * Only set this to `false` if you are certain that adding this
-    ///     edge won't create a cyle, such as when restoring a previously
-    ///     valid graph from a save state.
+    ///       edge won't create a cyle, such as when restoring a previously
+    ///       valid graph from a save state.
+    ///     * This is ignored while in batch mode (see
+    ///       `AudioGraphHelper::begin_batch()`), in which case the check
+    ///       is deferred to `AudioGraphHelper::end_batch()`.
     ///
     /// If successful, this returns the globally unique identifier assigned
     /// to this edge.
     ///
-    /// If this returns an error, then the audio graph has not been
-    /// modified.
-    pub fn add_edge(
-        &mut self,
+    /// If this returns an error, then the audio graph has not been
+    /// modified.
+    pub fn add_edge(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+        check_for_cycles: bool,
+    ) -> Result<EdgeID, AddEdgeError> {
+        self.insert_edge(
+            src_node_id,
+            src_port_id,
+            dst_node_id,
+            dst_port_id,
+            check_for_cycles,
+            false,
+            1.0,
+        )
+    }
+
+    /// Add an [Edge] (port connection) to the graph that carries a gain.
+    ///
+    /// The gain is surfaced on the destination's input buffer assignment
+    /// (or on the matching input of the inserted summing point) so the
+    /// processor can apply it when reading the source buffer. Plain
+    /// connections made with `AudioGraphHelper::add_edge()` use a gain
+    /// of `1.0`.
+    ///
+    /// See `AudioGraphHelper::add_edge()` for the other arguments and the
+    /// errors this can return.
+    pub fn add_edge_with_gain(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+        gain: f32,
+        check_for_cycles: bool,
+    ) -> Result<EdgeID, AddEdgeError> {
+        self.insert_edge(
+            src_node_id,
+            src_port_id,
+            dst_node_id,
+            dst_port_id,
+            check_for_cycles,
+            false,
+            gain,
+        )
+    }
+
+    /// Add an [Edge] (port connection) to the graph with a tag, a label for
+    /// organizing edges such as marking monitor sends. The tag can be
+    /// read back with `AudioGraphHelper::edge_tag()`, and every edge with a
+    /// tag can be found with `AudioGraphHelper::connections_with_tag()`.
+    ///
+    /// If the duplicate edge policy leaves an existing edge between the
+    /// same ports in place, the existing edge is tagged instead.
+    ///
+    /// See `AudioGraphHelper::add_edge()` for the other arguments and the
+    /// errors this can return.
+    pub fn add_edge_tagged(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+        tag: u32,
+        check_for_cycles: bool,
+    ) -> Result<EdgeID, AddEdgeError> {
+        let edge_id = self.add_edge(
+            src_node_id,
+            src_port_id,
+            dst_node_id,
+            dst_port_id,
+            check_for_cycles,
+        )?;
+        self.edges.get_mut(&edge_id).unwrap().tag = Some(tag);
+
+        Ok(edge_id)
+    }
+
+    /// Get the tag of the edge between the given source and destination
+    /// ports, as set with `AudioGraphHelper::add_edge_tagged()`.
+    ///
+    /// This returns `None` if the ports are not connected, or if the edge
+    /// between them does not have a tag.
+    pub fn edge_tag(&self, src: (NodeID, PortID), dst: (NodeID, PortID)) -> Option<u32> {
+        let edge_id = self.edge_keys.get(&(src.0, src.1, dst.0, dst.1))?;
+        self.edges[edge_id].tag
+    }
+
+    /// Iterate over the source and destination ports of every edge with the
+    /// given tag, in no particular order.
+    pub fn connections_with_tag(
+        &self,
+        tag: u32,
+    ) -> impl Iterator<Item = ((NodeID, PortID), (NodeID, PortID))> + '_ {
+        self.edges
+            .values()
+            .filter(move |e| e.tag == Some(tag))
+            .map(|e| ((e.src_node, e.src_port), (e.dst_node, e.dst_port)))
+    }
+
+    /// Add an [Edge] (port connection) to the graph, choosing the ports by
+    /// their index on each node instead of by their ID.
+    ///
+    /// * `src_port_index` - The index of the source port in the list of
+    ///   output ports of the source node, in the order they were added.
+    /// * `dst_port_index` - The index of the destination port in the list
+    ///   of input ports of the destination node, in the order they were
+    ///   added.
+    ///
+    /// See `AudioGraphHelper::add_edge()` for the other arguments and the
+    /// other errors this can return.
+    pub fn add_edge_by_index(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_index: usize,
+        dst_node_id: NodeID,
+        dst_port_index: usize,
+        check_for_cycles: bool,
+    ) -> Result<EdgeID, AddEdgeError> {
+        let src_port_id = self
+            .nodes
+            .get(&src_node_id)
+            .ok_or(AddEdgeError::SrcNodeNotFound(src_node_id))?
+            .outputs
+            .get(src_port_index)
+            .ok_or(AddEdgeError::SrcPortIndexOutOfBounds(
+                src_node_id,
+                src_port_index,
+            ))?
+            .id;
+        let dst_port_id = self
+            .nodes
+            .get(&dst_node_id)
+            .ok_or(AddEdgeError::DstNodeNotFound(dst_node_id))?
+            .inputs
+            .get(dst_port_index)
+            .ok_or(AddEdgeError::DstPortIndexOutOfBounds(
+                dst_node_id,
+                dst_port_index,
+            ))?
+            .id;
+
+        self.add_edge(
+            src_node_id,
+            src_port_id,
+            dst_node_id,
+            dst_port_id,
+            check_for_cycles,
+        )
+    }
+
+    /// Add a weak [Edge] (port connection) to the graph.
+    ///
+    /// A weak edge does not constrain the order of the schedule, so it
+    /// can never create a cycle. Instead, the source port is assigned a
+    /// buffer that is kept alive for the whole schedule, and the
+    /// destination port reads whatever was last written to it. If the
+    /// destination is scheduled before the source, this is the data from
+    /// the previous process cycle. This is useful for connections that can
+    /// tolerate one block of latency, such as modulation.
+    ///
+    /// * `src_node_id` - The ID of the source node.
+    /// * `src_port_id` - The ID of the source port. This must be an output
+    ///   port on the source node.
+    /// * `dst_node_id` - The ID of the destination node.
+    /// * `dst_port_id` - The ID of the destination port. This must be an
+    ///   input port on the destination node.
+    ///
+    /// If successful, this returns the globally unique identifier assigned
+    /// to this edge.
+    ///
+    /// If this returns an error, then the audio graph has not been
+    /// modified.
+    pub fn add_weak_edge(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+    ) -> Result<EdgeID, AddEdgeError> {
+        self.insert_edge(
+            src_node_id,
+            src_port_id,
+            dst_node_id,
+            dst_port_id,
+            false,
+            true,
+            1.0,
+        )
+    }
+
+    /// Register a converter from ports of type `from` to ports of type
+    /// `to`, for use by `AudioGraphHelper::add_converted_edge()`.
+    ///
+    /// The converter is a node with the given latency, an input port of
+    /// type `from` with the ID `PortID(0)`, and an output port of type `to`
+    /// with the ID `PortID(1)`. Registering a converter for the same pair
+    /// of types again replaces its latency.
+    pub fn register_type_converter(&mut self, from: TypeIdx, to: TypeIdx, latency: f64) {
+        self.type_converters.insert((from, to), latency);
+    }
+
+    /// Connect two ports of different types through a new converter node,
+    /// as registered with `AudioGraphHelper::register_type_converter()`.
+    ///
+    /// A converter node is added to the graph, along with an edge from the
+    /// source port to its input and an edge from its output to the
+    /// destination port. Both edges are added like with
+    /// `AudioGraphHelper::add_edge()`. The host should process the
+    /// converter node like any other node.
+    ///
+    /// On success, this returns the ID of the converter node, the ID of the
+    /// edge into it, and the ID of the edge out of it.
+    ///
+    /// This will return `AddEdgeError::NoTypeConverter` if no converter is
+    /// registered for the types of the two ports. If this returns an
+    /// error, then the converter node has been removed again and the audio
+    /// graph has not been modified, apart from the ID of the converter node
+    /// being used up. If a batch started with
+    /// `AudioGraphHelper::begin_batch()` is rolled back, the edges are
+    /// removed but the converter node is not.
+    pub fn add_converted_edge(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+        check_for_cycles: bool,
+    ) -> Result<(NodeID, EdgeID, EdgeID), AddEdgeError> {
+        let src_node = self
+            .nodes
+            .get(&src_node_id)
+            .ok_or(AddEdgeError::SrcNodeNotFound(src_node_id))?;
+        let dst_node = self
+            .nodes
+            .get(&dst_node_id)
+            .ok_or(AddEdgeError::DstNodeNotFound(dst_node_id))?;
+        let from = src_node
+            .outputs
+            .iter()
+            .find(|p| p.id == src_port_id)
+            .ok_or(AddEdgeError::SrcPortNotFound(src_node_id, src_port_id))?
+            .type_idx;
+        let to = dst_node
+            .inputs
+            .iter()
+            .find(|p| p.id == dst_port_id)
+            .ok_or(AddEdgeError::DstPortNotFound(dst_node_id, dst_port_id))?
+            .type_idx;
+        let latency = *self
+            .type_converters
+            .get(&(from, to))
+            .ok_or(AddEdgeError::NoTypeConverter { from, to })?;
+
+        let dirty = (self.needs_compile, self.connectivity_changed);
+        let converter = self.add_node(latency);
+        self.add_port(converter, PortID(0), from, true).unwrap();
+        self.add_port(converter, PortID(1), to, false).unwrap();
+
+        let result = self
+            .add_edge(src_node_id, src_port_id, converter, PortID(0), false)
+            .and_then(|in_edge| {
+                self.add_edge(
+                    converter,
+                    PortID(1),
+                    dst_node_id,
+                    dst_port_id,
+                    check_for_cycles,
+                )
+                .map(|out_edge| (converter, in_edge, out_edge))
+            });
+        if result.is_err() {
+            self.remove_node(converter).unwrap();
+            (self.needs_compile, self.connectivity_changed) = dirty;
+        }
+
+        result
+    }
+
+    /// Check whether or not `AudioGraphHelper::add_edge()` would succeed
+    /// with the given ports and `check_for_cycles` set, without modifying
+    /// the graph.
+    ///
+    /// This runs the same checks in the same order, and returns the error
+    /// of the first one that fails. This is useful for showing why two
+    /// ports cannot be connected before the user tries to connect them.
+    pub fn check_edge(
+        &self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+    ) -> Result<(), AddEdgeError> {
+        let replaced =
+            self.check_new_edge(src_node_id, src_port_id, dst_node_id, dst_port_id, false)?;
+        if replaced.is_some() && self.duplicate_edge_policy == DuplicateEdgePolicy::Ignore {
+            return Ok(());
+        }
+
+        let new_edge = Edge {
+            id: EdgeID(self.next_edge_id),
+            src_node: src_node_id,
+            src_port: src_port_id,
+            dst_node: dst_node_id,
+            dst_port: dst_port_id,
+            weak: false,
+            gain: 1.0,
+            tag: None,
+        };
+        let edges = self
+            .edges
+            .values()
+            .filter(|e| Some(e.id) != replaced)
+            .chain(std::iter::once(&new_edge));
+        let graph_ir =
+            GraphIR::preprocess(self.num_port_types, self.nodes.values(), edges).unwrap();
+        if graph_ir.tarjan() > 0 {
+            return Err(AddEdgeError::CycleDetected);
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_edge(
+        &mut self,
+        src_node_id: NodeID,
+        src_port_id: PortID,
+        dst_node_id: NodeID,
+        dst_port_id: PortID,
+        check_for_cycles: bool,
+        weak: bool,
+        gain: f32,
+    ) -> Result<EdgeID, AddEdgeError> {
+        let replaced =
+            self.check_new_edge(src_node_id, src_port_id, dst_node_id, dst_port_id, weak)?;
+        if let Some(edge_id) = replaced {
+            if self.duplicate_edge_policy == DuplicateEdgePolicy::Ignore {
+                return Ok(edge_id);
+            }
+        }
+        let edge_key = (src_node_id, src_port_id, dst_node_id, dst_port_id);
+
+        let new_edge_id = self.free_edge_ids.pop().unwrap_or_else(|| {
+            self.next_edge_id += 1;
+            EdgeID(self.next_edge_id - 1)
+        });
+
+        let new_edge = Edge {
+            id: new_edge_id,
+            src_node: src_node_id,
+            src_port: src_port_id,
+            dst_node: dst_node_id,
+            dst_port: dst_port_id,
+            weak,
+            gain,
+            tag: None,
+        };
+
+        let replaced = replaced.and_then(|edge_id| self.remove_edge_entry(edge_id));
+        self.edges.insert(new_edge_id, new_edge);
+        self.edge_keys.insert(edge_key, new_edge_id);
+
+        // Weak edges never constrain the schedule, so they cannot create a cycle.
+        if !weak {
+            if let Some(batch_edges) = &mut self.batch_edges {
+                batch_edges.push(new_edge_id);
+                self.batch_replaced.extend(replaced);
+            } else if check_for_cycles && self.cycle_check_enabled && self.cycle_detected() {
+                self.remove_edge_entry(new_edge_id);
+                if let Some(edge) = replaced {
+                    self.edges.insert(edge.id, edge);
+                    self.edge_keys.insert(edge_key, edge.id);
+                }
+
+                return Err(AddEdgeError::CycleDetected);
+            }
+        }
+
+        self.needs_compile = true;
+        self.connectivity_changed = true;
+
+        Ok(new_edge_id)
+    }
+
+    /// Run every check of `insert_edge()` that does not need the edge to be
+    /// in the graph, in order.
+    ///
+    /// If an edge between the same ports already exists and the duplicate
+    /// edge policy allows it, this returns its ID.
+    fn check_new_edge(
+        &self,
         src_node_id: NodeID,
         src_port_id: PortID,
         dst_node_id: NodeID,
         dst_port_id: PortID,
-        check_for_cycles: bool,
-    ) -> Result<EdgeID, AddEdgeError> {
+        weak: bool,
+    ) -> Result<Option<EdgeID>, AddEdgeError> {
         let src_node = self
             .nodes
             .get(&src_node_id)
@@ -304,73 +1798,679 @@ impl AudioGraphHelper {
             });
         }
 
-        for e in self.edges.values() {
-            if e.src_node == src_node_id
-                && e.dst_node == dst_node_id
-                && e.src_port == src_port_id
-                && e.dst_port == dst_port_id
-            {
-                return Err(AddEdgeError::EdgeAlreadyExists(*e));
+        let edge_key = (src_node_id, src_port_id, dst_node_id, dst_port_id);
+        let mut replaced = None;
+        if let Some(edge_id) = self.edge_keys.get(&edge_key).copied() {
+            match self.duplicate_edge_policy {
+                DuplicateEdgePolicy::Error => {
+                    return Err(AddEdgeError::EdgeAlreadyExists(self.edges[&edge_id]));
+                }
+                DuplicateEdgePolicy::Ignore => return Ok(Some(edge_id)),
+                DuplicateEdgePolicy::Replace => {}
             }
+            replaced = Some(edge_id);
         }
 
-        if src_node_id == dst_node_id {
+        if !weak && src_node_id == dst_node_id {
             return Err(AddEdgeError::CycleDetected);
         }
 
-        let new_edge_id = self.free_edge_ids.pop().unwrap_or_else(|| {
-            self.next_edge_id += 1;
-            EdgeID(self.next_edge_id - 1)
-        });
+        if let Some(validator) = &self.edge_validator {
+            validator(self, src_node_id, src_port_id, dst_node_id, dst_port_id)?;
+        }
 
-        let new_edge = Edge {
-            id: new_edge_id,
-            src_node: src_node_id,
-            src_port: src_port.id,
-            dst_node: dst_node_id,
-            dst_port: dst_port.id,
-        };
+        Ok(replaced)
+    }
 
-        self.edges.insert(new_edge_id, new_edge);
+    /// Remove the given [Edge] (port connection) from the graph.
+    ///
+    /// This will return an error if the given edge does not exist in
+    /// the graph. In this case the graph has not been modified.
+    #[allow(clippy::result_unit_err)]
+    pub fn remove_edge(&mut self, edge_id: EdgeID) -> Result<(), ()> {
+        if self.remove_edge_entry(edge_id).is_none() {
+            return Err(());
+        }
+
+        self.needs_compile = true;
+        self.connectivity_changed = true;
 
-        if check_for_cycles {
-            if self.cycle_detected() {
-                self.edges.remove(&new_edge_id);
+        Ok(())
+    }
 
-                return Err(AddEdgeError::CycleDetected);
+    /// Solo an output port by making it the only thing connected to the
+    /// given master input.
+    ///
+    /// Every edge into `master_in` is removed, and `port` is connected to
+    /// it with a new edge, which is checked for cycles. The returned token
+    /// restores the original edges when passed to
+    /// `AudioGraphHelper::unsolo()`. If the graph is compacted before
+    /// then, the token must be updated with `SoloToken::remap()`.
+    ///
+    /// See `AudioGraphHelper::add_edge()` for the errors this can return.
+    /// In this case the graph has not been modified.
+    pub fn solo_output(
+        &mut self,
+        port: (NodeID, PortID),
+        master_in: (NodeID, PortID),
+    ) -> Result<SoloToken, AddEdgeError> {
+        let mut removed: Vec<Edge> = self
+            .edges
+            .values()
+            .filter(|e| (e.dst_node, e.dst_port) == master_in)
+            .copied()
+            .collect();
+        removed.sort_unstable_by_key(|e| e.id.0);
+        for edge in removed.iter() {
+            self.remove_edge_entry(edge.id);
+        }
+
+        match self.add_edge(port.0, port.1, master_in.0, master_in.1, true) {
+            Ok(edge_id) => Ok(SoloToken {
+                soloed: Some(edge_id),
+                removed,
+                stale: vec![],
+            }),
+            Err(e) => {
+                for edge in removed {
+                    self.restore_edge_entry(edge);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Undo `AudioGraphHelper::solo_output()`, removing the edge it added
+    /// and adding back the edges it removed, with the same ports, gain and
+    /// tag. The restored edges are given new IDs, like any new edge. If the
+    /// edge that was added is already gone, then no other edge is removed,
+    /// even one between the same ports.
+    ///
+    /// If the graph was changed in the meantime so that an edge can't be
+    /// added back, because one of its ports was removed, the ports were
+    /// connected again, or the edge validator rejects it, then that edge is
+    /// skipped. The skipped edges are returned with their original IDs.
+    /// Restored edges are not checked for cycles.
+    pub fn unsolo(&mut self, token: SoloToken) -> Vec<Edge> {
+        if let Some(edge_id) = token.soloed {
+            self.remove_edge_entry(edge_id);
+        }
+
+        let mut skipped = token.stale;
+        for edge in token.removed {
+            let key = (edge.src_node, edge.src_port, edge.dst_node, edge.dst_port);
+            if self.edge_keys.contains_key(&key) {
+                skipped.push(edge);
+                continue;
+            }
+
+            match self.insert_edge(
+                edge.src_node,
+                edge.src_port,
+                edge.dst_node,
+                edge.dst_port,
+                false,
+                edge.weak,
+                edge.gain,
+            ) {
+                Ok(edge_id) => self.edges.get_mut(&edge_id).unwrap().tag = edge.tag,
+                Err(_) => skipped.push(edge),
             }
         }
 
         self.needs_compile = true;
+        self.connectivity_changed = true;
 
-        Ok(new_edge_id)
+        skipped
     }
 
-    /// Remove the given [Edge] (port connection) from the graph.
+    /// Enter batch mode.
     ///
-    /// This will return an error if the given edge does not exist in
-    /// the graph. In this case the graph has not been modified.
-    pub fn remove_edge(&mut self, edge_id: EdgeID) -> Result<(), ()> {
-        if self.edges.remove(&edge_id).is_none() {
-            return Err(());
+    /// While in batch mode, `AudioGraphHelper::add_edge()` skips the
+    /// per-edge cycle check. Instead, a single check over the whole graph
+    /// is run when `AudioGraphHelper::end_batch()` is called. This is much
+    /// faster when adding a large number of edges at once, such as when
+    /// loading a large graph.
+    ///
+    /// Calling this while already in batch mode does nothing.
+    pub fn begin_batch(&mut self) {
+        if self.batch_edges.is_none() {
+            self.batch_edges = Some(Vec::new());
         }
+    }
 
-        self.needs_compile = true;
+    /// Leave batch mode, checking the graph for cycles.
+    ///
+    /// If a cycle was detected, then every edge that was added since
+    /// `AudioGraphHelper::begin_batch()` was called is removed from the
+    /// graph, and an error is returned. The edges that those edges replaced
+    /// (see [DuplicateEdgePolicy::Replace]) are restored with their old
+    /// IDs, unless one of their ports was removed or their ports were
+    /// connected again in the meantime, or `AudioGraphHelper::compact()`
+    /// was called during the batch.
+    ///
+    /// Calling this while not in batch mode does nothing.
+    pub fn end_batch(&mut self) -> Result<(), AddEdgeError> {
+        let batch_edges = if let Some(batch_edges) = self.batch_edges.take() {
+            batch_edges
+        } else {
+            return Ok(());
+        };
+        let replaced = std::mem::take(&mut self.batch_replaced);
+
+        if self.cycle_detected() {
+            for edge_id in batch_edges.iter() {
+                self.remove_edge_entry(*edge_id);
+            }
+            // An edge that was itself added in the batch stays removed, and
+            // a weak edge added in the batch keeps its connection.
+            let batch_edges: FnvHashSet<EdgeID> = batch_edges.into_iter().collect();
+            for edge in replaced {
+                let edge_key = (edge.src_node, edge.src_port, edge.dst_node, edge.dst_port);
+                if !batch_edges.contains(&edge.id)
+                    && !self.edge_keys.contains_key(&edge_key)
+                    && self.contains_port(edge.src_node, edge.src_port)
+                    && self.contains_port(edge.dst_node, edge.dst_port)
+                {
+                    self.restore_edge_entry(edge);
+                }
+            }
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+
+            return Err(AddEdgeError::CycleDetected);
+        }
 
         Ok(())
     }
 
+    /// Returns `true` if the helper is currently in batch mode.
+    pub fn in_batch(&self) -> bool {
+        self.batch_edges.is_some()
+    }
+
     /// Compile the graph into a schedule.
+    ///
+    /// If only the latencies of nodes have changed since the last compile,
+    /// and the same edges still need delay compensation, then the previous
+    /// buffer assignments are reused and only the latency values of the
+    /// schedule are updated.
+    ///
+    /// Compiling a graph with no nodes, including one whose nodes have all
+    /// been removed, returns an empty schedule that needs no buffers.
+    ///
+    /// The variants of this method, such as
+    /// `AudioGraphHelper::compile_strict()`, share this cache. The queries
+    /// about the last compile, such as `AudioGraphHelper::schedule_delta()`,
+    /// describe the last one of these calls that succeeded.
     pub fn compile(&mut self) -> Result<CompiledSchedule, CompileGraphError> {
-        self.needs_compile = false;
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir.sort_topologically()?.solve_latency_requirements())
+        })
+    }
 
-        crate::compile(
-            self.num_port_types,
-            self.nodes.values(),
-            self.edges.values(),
+    /// Returns `true` if the last call to `AudioGraphHelper::compile()`
+    /// scheduled the nodes in a different order than the call before it,
+    /// or if there was no call before it.
+    ///
+    /// Hosts that keep per-node state keyed by schedule position only need
+    /// to re-bind it when this returns `true`.
+    pub fn order_changed_since_last_compile(&self) -> bool {
+        self.order_changed
+    }
+
+    /// List the nodes whose buffer assignments or delay compensation were
+    /// changed by the last call to `AudioGraphHelper::compile()`, in
+    /// schedule order. If there was no compile before it, this lists every
+    /// node.
+    ///
+    /// The host only needs to update the bindings of these nodes. See
+    /// `CompiledSchedule::changed_nodes()`.
+    pub fn schedule_delta(&self) -> &[NodeID] {
+        &self.schedule_delta
+    }
+
+    /// Returns `true` if the last call to `AudioGraphHelper::compile()`
+    /// inserted any delays to compensate for latency, for example to show
+    /// that delay compensation is active in the current graph.
+    ///
+    /// This returns `false` if the graph has not been compiled yet.
+    pub fn has_delay_compensation(&self) -> bool {
+        match &self.last_schedule {
+            Some(schedule) => !schedule.delays.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Iterate over every delay inserted by the last call to
+    /// `AudioGraphHelper::compile()` to compensate for latency, as the
+    /// `(node, port)` the delayed edge comes from, the `(node, port)` it
+    /// goes to, and the amount of delay.
+    ///
+    /// This is empty if the graph has not been compiled yet.
+    pub fn delay_compensations(
+        &self,
+    ) -> impl Iterator<Item = ((NodeID, PortID), (NodeID, PortID), f64)> + '_ {
+        self.last_schedule
+            .iter()
+            .flat_map(|schedule| schedule.delays.iter())
+            .map(|d| {
+                (
+                    (d.edge.src_node, d.edge.src_port),
+                    (d.edge.dst_node, d.edge.dst_port),
+                    d.delay,
+                )
+            })
+    }
+
+    /// The total amount of delay inserted by the last call to
+    /// `AudioGraphHelper::compile()` to compensate for latency, which is
+    /// how much delay line storage the engine needs for each channel.
+    ///
+    /// This is `0.0` if the graph has not been compiled yet.
+    pub fn total_compensation_samples(&self) -> f64 {
+        self.delay_compensations().map(|(_, _, delay)| delay).sum()
+    }
+
+    /// Set whether or not `AudioGraphHelper::compile()` should record every
+    /// buffer acquired and released while assigning buffers, which can be
+    /// read back with `AudioGraphHelper::last_allocation_log()`. This is
+    /// disabled by default.
+    pub fn set_record_allocation_events(&mut self, record: bool) {
+        if record {
+            if self.allocation_log.is_none() {
+                self.allocation_log = Some(Vec::new());
+                // Make sure the next compile assigns buffers again.
+                self.connectivity_changed = true;
+            }
+        } else {
+            self.allocation_log = None;
+        }
+    }
+
+    /// The buffers acquired and released by the last call to
+    /// `AudioGraphHelper::compile()`, in schedule order.
+    ///
+    /// This is empty unless recording was enabled with
+    /// `AudioGraphHelper::set_record_allocation_events()`. If the last
+    /// compile only updated latencies, this is the log of the compile that
+    /// made the buffer assignments it reused.
+    pub fn last_allocation_log(&self) -> &[BufferEvent] {
+        self.allocation_log.as_deref().unwrap_or(&[])
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// calling `f` with each entry of the schedule in order as it is
+    /// produced.
+    ///
+    /// This lets the caller build its own per-node data while the schedule
+    /// is being built, instead of iterating over the schedule afterwards.
+    pub fn compile_with_callback(
+        &mut self,
+        f: impl FnMut(&ScheduleEntry),
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with_entries(
+            |graph_ir| Ok(graph_ir.sort_topologically()?.solve_latency_requirements()),
+            f,
         )
     }
 
+    /// Compile the graph like `AudioGraphHelper::compile()`, but hand each
+    /// entry of the schedule to `f` in order instead of returning a
+    /// [CompiledSchedule].
+    ///
+    /// This is useful for a consumer that takes ownership of each entry,
+    /// since the schedule is never collected. Because of that, it is also
+    /// not cached: the queries about the last compile, such as
+    /// `AudioGraphHelper::schedule_delta()`, still describe the last call
+    /// to `AudioGraphHelper::compile()` or one of its other variants. On
+    /// success, this returns the total number of buffers required to
+    /// allocate, for each type of port.
+    ///
+    /// If this returns an error, then `f` has not been called.
+    pub fn compile_stream(
+        &mut self,
+        f: impl FnMut(ScheduleEntry),
+    ) -> Result<Vec<usize>, CompileGraphError> {
+        let graph_ir = self
+            .prepare_compile(|graph_ir| {
+                Ok(graph_ir.sort_topologically()?.solve_latency_requirements())
+            })?
+            .solve_buffer_requirements()?;
+        self.store_allocation_log(&graph_ir);
+        self.needs_compile = false;
+
+        Ok(graph_ir.stream(f))
+    }
+
+    /// Compile the graph into a flat list of operations, with a copy into
+    /// a scratch buffer before every node input that shares its buffer
+    /// with another reader (see `CompiledSchedule::with_copies()`).
+    ///
+    /// This is for processors that can't read and write the same buffer,
+    /// or that write into their input buffers.
+    pub fn compile_with_copies(&mut self) -> Result<OpSchedule, CompileGraphError> {
+        Ok(self.compile()?.with_copies())
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// returning an error if any required input port has no incoming edges.
+    pub fn compile_strict(&mut self) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .validate_required_inputs()?
+                .sort_topologically()?
+                .solve_latency_requirements())
+        })
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// for a host that processes the graph in blocks of `block_size` frames.
+    ///
+    /// Along with the schedule, this returns every delay in
+    /// `CompiledSchedule::delays` split into whole blocks and the remaining
+    /// frames within a block, in the same order (see
+    /// `InsertedDelay::split_at_block_size()`). The delays themselves are
+    /// still in frames.
+    ///
+    /// This will return `CompileGraphError::ZeroBlockSize` if `block_size`
+    /// is `0`, and `CompileGraphError::IncompatibleBlockSize` if
+    /// `block_size` is not a multiple of the block size required by one of
+    /// the nodes, as set with `AudioGraphHelper::set_node_block_requirement()`.
+    pub fn compile_with_block_size(
+        &mut self,
+        block_size: u64,
+    ) -> Result<(CompiledSchedule, Vec<(u64, f64)>), CompileGraphError> {
+        let schedule = self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .validate_block_size(block_size)?
+                .sort_topologically()?
+                .solve_latency_requirements())
+        })?;
+
+        // Compensation delays are never negative, so only a delay that is
+        // not finite can't be split. It is passed through as it is.
+        let blocks = schedule
+            .delays
+            .iter()
+            .map(|delay| {
+                delay
+                    .split_at_block_size(block_size)
+                    .unwrap_or((0, delay.delay))
+            })
+            .collect();
+
+        Ok((schedule, blocks))
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// as long as its total latency is no more than `max_latency`.
+    ///
+    /// This will return `CompileGraphError::LatencyBudgetExceeded` if the
+    /// latency of `AudioGraphHelper::critical_path()` is more than
+    /// `max_latency`, so that a host can reject a change that would add too
+    /// much latency before sending the schedule to the audio thread.
+    pub fn compile_with_latency_limit(
+        &mut self,
+        max_latency: f64,
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .validate_latency_limit(max_latency)?
+                .sort_topologically()?
+                .solve_latency_requirements())
+        })
+    }
+
+    /// Compile only the given source nodes and the nodes downstream of them
+    /// into a schedule, like `AudioGraphHelper::compile()`.
+    ///
+    /// This is useful when the host knows which nodes are real sources,
+    /// such as hardware inputs, so that nodes which only look like sources
+    /// because their inputs are not connected yet are left out.
+    ///
+    /// This will return `CompileGraphError::SourceHasDependencies` if one
+    /// of the sources has an incoming edge, and
+    /// `CompileGraphError::UnlistedSource` if a node downstream of the
+    /// sources depends on a node that is not.
+    pub fn compile_from_sources(
+        &mut self,
+        sources: &[NodeID],
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .retain_descendants(sources)?
+                .sort_topologically()?
+                .solve_latency_requirements())
+        })
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// but when more than one node is ready to be scheduled, schedule the
+    /// one with the smallest `key` first.
+    ///
+    /// Every node is still scheduled after all of the nodes it depends on.
+    /// Node priorities are ignored, unless they are part of the key. For
+    /// example, `|node| node.id.0` gives a stable order for dumps that can
+    /// be compared between runs.
+    pub fn compile_sorted_by<K: Ord>(
+        &mut self,
+        key: impl Fn(&Node) -> K,
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .sort_topologically_by(key)?
+                .solve_latency_requirements())
+        })
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// but when more than one node is ready to be scheduled, schedule the
+    /// ones with ports of a higher priority type first.
+    ///
+    /// `type_priorities` is indexed by port type, and types that are not in
+    /// it have a priority of `0`. The type priority of a node is the highest
+    /// priority of the types of its ports, and nodes with the same type
+    /// priority are ordered by their own priority. For example, giving the
+    /// event type a higher priority than the audio type resolves note
+    /// events before the audio nodes that are ready at the same time.
+    pub fn compile_with_type_priority(
+        &mut self,
+        type_priorities: &[u8],
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_sorted_by(|node| {
+            let type_priority = node
+                .inputs
+                .iter()
+                .chain(node.outputs.iter())
+                .filter_map(|p| type_priorities.get(p.type_idx.0).copied())
+                .max()
+                .unwrap_or(0);
+
+            (Reverse(type_priority), Reverse(node.priority))
+        })
+    }
+
+    /// Compile the graph into a schedule that uses fewer buffers at the
+    /// same time, at the cost of a slower compile.
+    ///
+    /// The order of independent nodes is chosen so that buffers are freed
+    /// as early as possible. This is useful on memory-constrained targets.
+    /// Every node is still scheduled after all of the nodes it depends on,
+    /// and delay compensation is the same as with
+    /// `AudioGraphHelper::compile()`.
+    pub fn compile_min_buffers(&mut self) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with(|graph_ir| {
+            Ok(graph_ir
+                .sort_topologically_min_buffers()?
+                .solve_latency_requirements())
+        })
+    }
+
+    /// Compile each connected component of the graph into its own
+    /// schedule, so that they can be processed in parallel.
+    ///
+    /// Two nodes are in the same component if there is a path of edges
+    /// (including weak edges) between them, ignoring direction. The
+    /// schedules are sorted by the lowest node ID in their component.
+    ///
+    /// Buffer indices are per component: each schedule starts counting
+    /// its buffers from zero, so the engine must give each one its own
+    /// set of buffers.
+    ///
+    /// Like `AudioGraphHelper::compile_up_to()`, this does not affect the
+    /// schedule returned by `AudioGraphHelper::compile()` or the queries
+    /// about the last compile, and `AudioGraphHelper::needs_compile()` is
+    /// left unchanged.
+    pub fn compile_by_component(&self) -> Result<Vec<CompiledSchedule>, CompileGraphError> {
+        self.compile_input()
+            .split_components()
+            .into_iter()
+            .map(|graph_ir| {
+                Ok(graph_ir
+                    .sort_topologically()?
+                    .solve_latency_requirements()
+                    .solve_buffer_requirements()?
+                    .merge())
+            })
+            .collect()
+    }
+
+    /// Compile the graph into a schedule like `AudioGraphHelper::compile()`,
+    /// and partition the schedule by the process group of each node, as set
+    /// with `AudioGraphHelper::set_node_group()`.
+    ///
+    /// Along with the schedule, this returns every group that has a node
+    /// in the schedule, sorted by group, with the indices of its entries in
+    /// `CompiledSchedule::schedule` in order. Inserted delays and sums
+    /// belong to the group of the node they feed. The schedule is in a
+    /// single global topological order, so edges between groups are
+    /// allowed: the engine must only wait for the entries of other groups
+    /// that come earlier in the schedule.
+    pub fn compile_grouped(
+        &mut self,
+    ) -> Result<(CompiledSchedule, Vec<(u32, Vec<usize>)>), CompileGraphError> {
+        let schedule = self.compile()?;
+
+        // Delays and sums come right before the node they feed in the
+        // schedule, so walk it backwards.
+        let mut groups: FnvHashMap<u32, Vec<usize>> = FnvHashMap::default();
+        let mut group = 0;
+        for (i, entry) in schedule.schedule.iter().enumerate().rev() {
+            if let ScheduleEntry::Node(node) = entry {
+                group = self.nodes[&node.id].group;
+            }
+            groups.entry(group).or_default().push(i);
+        }
+
+        let mut groups: Vec<(u32, Vec<usize>)> = groups
+            .into_iter()
+            .map(|(group, mut entries)| {
+                entries.reverse();
+                (group, entries)
+            })
+            .collect();
+        groups.sort_unstable_by_key(|(group, _)| *group);
+
+        Ok((schedule, groups))
+    }
+
+    /// Check that every node that would be compiled appears in the given
+    /// schedule.
+    ///
+    /// Nodes left out by dead node elimination (see
+    /// `AudioGraphHelper::set_eliminate_dead_nodes()`) are not expected to
+    /// be in the schedule. This is a guard against bugs that silently drop
+    /// nodes, and against using a schedule that was compiled before nodes
+    /// were added.
+    pub fn assert_complete_schedule(
+        &self,
+        schedule: &CompiledSchedule,
+    ) -> Result<(), CompileGraphError> {
+        self.compile_input().assert_complete_schedule(schedule)
+    }
+
+    /// Set a custom rule that every new edge must pass, such as a rule
+    /// that a master bus can't be connected into an instrument.
+    ///
+    /// The validator is called when adding an edge, after the ports and
+    /// their types have been checked, and before the edge is added to the
+    /// graph. If it returns an error, then the edge is not added and the
+    /// error is returned to the caller. `AddEdgeError::Custom` can be used
+    /// to describe the rule that was broken.
+    ///
+    /// Passing `None` removes the validator.
+    pub fn set_edge_validator(&mut self, validator: Option<Box<EdgeValidator>>) {
+        self.edge_validator = validator;
+    }
+
+    /// Compile a schedule that only contains the given node and the nodes
+    /// it depends on, for example to render the graph up to a monitoring
+    /// point.
+    ///
+    /// Buffers and delay compensation are solved for the partial graph as
+    /// if the other nodes did not exist. This does not affect the schedule
+    /// returned by `AudioGraphHelper::compile()`, and it ignores dead node
+    /// elimination. If the node does not exist, the schedule is empty.
+    pub fn compile_up_to(&self, node_id: NodeID) -> Result<CompiledSchedule, CompileGraphError> {
+        Ok(self
+            .graph_ir()
+            .retain_ancestors(node_id)
+            .sort_topologically()?
+            .solve_latency_requirements()
+            .solve_buffer_requirements()?
+            .merge())
+    }
+
+    /// Set what adding an edge between two ports that are already connected
+    /// does. By default this returns `AddEdgeError::EdgeAlreadyExists`.
+    pub fn set_duplicate_edge_policy(&mut self, policy: DuplicateEdgePolicy) {
+        self.duplicate_edge_policy = policy;
+    }
+
+    /// Set whether or not compiling the graph should leave out every node
+    /// that does not contribute to the output of the graph.
+    ///
+    /// A node contributes to the output if it has no output ports (such as
+    /// a node that writes to an audio device), if it has a pinned output
+    /// port, or if one of its output ports is connected to a node that
+    /// contributes to the output. This is disabled by default.
+    pub fn set_eliminate_dead_nodes(&mut self, eliminate: bool) {
+        if self.eliminate_dead_nodes != eliminate {
+            self.eliminate_dead_nodes = eliminate;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+    }
+
+    /// Set whether or not adding an edge checks for cycles. This is enabled
+    /// by default.
+    ///
+    /// While disabled, `check_for_cycles` is ignored by
+    /// `AudioGraphHelper::add_edge()` and friends. This speeds up loading a
+    /// graph that is known to have no cycles, such as one that was saved
+    /// earlier. A cycle added in the meantime is still caught when the
+    /// graph is compiled, which returns `CompileGraphError::CycleDetected`.
+    pub fn set_cycle_check_enabled(&mut self, enabled: bool) {
+        self.cycle_check_enabled = enabled;
+    }
+
+    /// Set whether or not the edges of each node should be sorted by their
+    /// ports before compiling or analyzing the graph.
+    ///
+    /// By default, the order of the edges depends on the order they were
+    /// added in, which can change the schedule and its buffer assignments.
+    /// With this enabled, the same graph always compiles to the same
+    /// schedule no matter what order its edges were added in. This is
+    /// disabled by default.
+    pub fn set_sort_edges(&mut self, sort: bool) {
+        if self.sort_edges != sort {
+            self.sort_edges = sort;
+            self.needs_compile = true;
+            self.connectivity_changed = true;
+        }
+    }
+
     /// Returns `true` if `AudioGraphHelper::compile()` should be called
     /// again because the state of the graph has changed since the last
     /// compile.
@@ -378,6 +2478,81 @@ impl AudioGraphHelper {
         self.needs_compile
     }
 
+    /// A hash of the connectivity of the graph.
+    ///
+    /// This covers the ID, priority and bus flag of every node, the ID, type
+    /// and flags of every port, and the ports, gain and weak flag of every
+    /// edge. Two graphs with the same connectivity always have the same
+    /// hash, regardless of the order in which they were built. This can be
+    /// used to cheaply check whether the connectivity has changed between
+    /// two edits.
+    ///
+    /// Because of that, the order in which the ports of a node and the
+    /// edges were added is not hashed, even though it can change the
+    /// buffer assignments and the order of independent nodes in the
+    /// schedule. Sorting edges (see `AudioGraphHelper::set_sort_edges()`)
+    /// removes the effect of the edge order, but not of the port order, so
+    /// two graphs with the same hash can still be compiled into different
+    /// schedules.
+    ///
+    /// Some properties are left out on purpose:
+    ///     * The latencies of nodes, since a latency change only updates
+    ///       the delays of the last schedule (see
+    ///       `AudioGraphHelper::compile()`).
+    ///     * The IDs of edges, and edge tags, which don't change the
+    ///       schedule.
+    ///     * Block size requirements, process groups and required input
+    ///       ports, which are only used by the compile variants that check
+    ///       or partition the schedule.
+    pub fn topology_hash(&self) -> u64 {
+        let mut nodes: Vec<&Node> = self.nodes.values().collect();
+        nodes.sort_unstable_by_key(|n| n.id.0);
+
+        let mut edges: Vec<(u32, u32, u32, u32, bool, u32)> = self
+            .edges
+            .values()
+            .map(|e| {
+                (
+                    e.src_node.0,
+                    e.src_port.0,
+                    e.dst_node.0,
+                    e.dst_port.0,
+                    e.weak,
+                    e.gain.to_bits(),
+                )
+            })
+            .collect();
+        edges.sort_unstable();
+
+        let mut hasher = FnvHasher::default();
+        for node in nodes.iter() {
+            node.id.hash(&mut hasher);
+            node.priority.hash(&mut hasher);
+            node.is_bus.hash(&mut hasher);
+            for (ports, is_input) in [(&node.inputs, true), (&node.outputs, false)].iter() {
+                let mut ports: Vec<(u32, usize, bool, bool, bool, bool)> = ports
+                    .iter()
+                    .map(|p| {
+                        (
+                            p.id.0,
+                            p.type_idx.0,
+                            p.pinned,
+                            p.constant,
+                            p.sidechain,
+                            p.clear_before_write,
+                        )
+                    })
+                    .collect();
+                ports.sort_unstable();
+                is_input.hash(&mut hasher);
+                ports.hash(&mut hasher);
+            }
+        }
+        edges.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
     /// The total number of port types that can exist in this audio
     /// graph. For example, if your graph can have an audio port type
     /// and an event port type, then this should be `2`.
@@ -387,7 +2562,62 @@ impl AudioGraphHelper {
         self.num_port_types
     }
 
-    fn remove_edges_with_port(&mut self, node_id: NodeID, port_id: PortID) -> Vec<EdgeID> {
+    /// The total number of edge entries stored by the helper and by the
+    /// [GraphIR] built from it, for checking that every edge is stored
+    /// exactly once in each index and once at each end in the [GraphIR].
+    ///
+    /// This returns the number of edges, the number of entries in the
+    /// index of edges by port, and the number of adjacency list entries in
+    /// the [GraphIR].
+    #[cfg(test)]
+    pub(crate) fn raw_edge_slot_count(&self) -> (usize, usize, usize) {
+        (
+            self.edges.len(),
+            self.edge_keys.len(),
+            self.graph_ir().raw_edge_slot_count(),
+        )
+    }
+
+    fn unique_neighbors(
+        &self,
+        node_id: NodeID,
+        neighbor: impl Fn(&Edge) -> Option<NodeID>,
+    ) -> Result<Vec<NodeID>, ()> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(());
+        }
+
+        let mut neighbors = self
+            .edges
+            .values()
+            .filter(|edge| !edge.weak)
+            .filter_map(neighbor)
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable_by_key(|id| id.0);
+        neighbors.dedup();
+
+        Ok(neighbors)
+    }
+
+    fn port_mut(&mut self, node_id: NodeID, port_id: PortID) -> Result<&mut Port, FindPortError> {
+        let node = self
+            .nodes
+            .get_mut(&node_id)
+            .ok_or(FindPortError::NodeNotFound(node_id))?;
+
+        node.inputs
+            .iter_mut()
+            .chain(node.outputs.iter_mut())
+            .find(|p| p.id == port_id)
+            .ok_or(FindPortError::PortNotFound(node_id, port_id))
+    }
+
+    fn remove_edges_with_port(
+        &mut self,
+        node_id: NodeID,
+        port_id: PortID,
+        removed: &mut Vec<Edge>,
+    ) {
         let mut edges_to_remove: Vec<EdgeID> = Vec::new();
 
         // Remove all existing edges which have this port.
@@ -400,20 +2630,140 @@ impl AudioGraphHelper {
         }
 
         for edge_id in edges_to_remove.iter() {
-            self.edges.remove(edge_id);
+            removed.extend(self.remove_edge_entry(*edge_id));
+        }
+    }
+
+    fn remove_edge_entry(&mut self, edge_id: EdgeID) -> Option<Edge> {
+        let edge = self.edges.remove(&edge_id)?;
+        self.edge_keys
+            .remove(&(edge.src_node, edge.src_port, edge.dst_node, edge.dst_port));
+        Some(edge)
+    }
+
+    fn restore_edge_entry(&mut self, edge: Edge) {
+        self.edge_keys.insert(
+            (edge.src_node, edge.src_port, edge.dst_node, edge.dst_port),
+            edge.id,
+        );
+        self.edges.insert(edge.id, edge);
+    }
+
+    /// Store a copy of a schedule that was compiled from the current
+    /// connectivity of the graph, and record how it differs from the
+    /// previous one.
+    fn cache_schedule(&mut self, schedule: &CompiledSchedule) {
+        match &self.last_schedule {
+            Some(last_schedule) => {
+                self.order_changed = !last_schedule.node_ids().eq(schedule.node_ids());
+                self.schedule_delta = schedule.changed_nodes(last_schedule);
+            }
+            None => {
+                self.order_changed = true;
+                self.schedule_delta = schedule.node_ids().collect();
+            }
+        }
+
+        match &mut self.last_schedule {
+            Some(last_schedule) => last_schedule.clone_from(schedule),
+            None => self.last_schedule = Some(schedule.clone()),
+        }
+        self.connectivity_changed = false;
+    }
+
+    /// Run `passes` over the compile input, which must sort the graph and
+    /// solve its latency requirements, and then finish compiling it.
+    ///
+    /// If only latencies changed since the last compile and the order of
+    /// the schedule is the same, then the buffer assignments of the cached
+    /// schedule are reused. The dirty flags are only cleared, and the
+    /// schedule is only cached, once the compile succeeds.
+    fn compile_with(
+        &mut self,
+        passes: impl FnOnce(GraphIR) -> Result<GraphIR, CompileGraphError>,
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        self.compile_with_entries(passes, |_| {})
+    }
+
+    /// Like `AudioGraphHelper::compile_with()`, calling `f` with each entry
+    /// of the schedule in order. `f` is only called once the compile can no
+    /// longer fail.
+    fn compile_with_entries(
+        &mut self,
+        passes: impl FnOnce(GraphIR) -> Result<GraphIR, CompileGraphError>,
+        mut f: impl FnMut(&ScheduleEntry),
+    ) -> Result<CompiledSchedule, CompileGraphError> {
+        let graph_ir = self.prepare_compile(passes)?;
+
+        if !self.connectivity_changed {
+            if let Some(schedule) = &mut self.last_schedule {
+                if let Some(changed) = graph_ir.update_latencies(schedule) {
+                    schedule.schedule.iter().for_each(&mut f);
+                    self.needs_compile = false;
+                    self.order_changed = false;
+                    self.schedule_delta = changed;
+                    return Ok(schedule.clone());
+                }
+            }
+        }
+
+        let graph_ir = graph_ir.solve_buffer_requirements()?;
+        self.store_allocation_log(&graph_ir);
+        let schedule = graph_ir.merge_with_callback(f);
+
+        self.needs_compile = false;
+        self.cache_schedule(&schedule);
+
+        Ok(schedule)
+    }
+
+    /// Run `passes` over the compile input, recording allocation events if
+    /// the allocation log is enabled.
+    fn prepare_compile(
+        &self,
+        passes: impl FnOnce(GraphIR) -> Result<GraphIR, CompileGraphError>,
+    ) -> Result<GraphIR, CompileGraphError> {
+        let mut graph_ir = self.compile_input();
+        if self.allocation_log.is_some() {
+            graph_ir = graph_ir.record_allocation_events();
         }
+        passes(graph_ir)
+    }
 
-        edges_to_remove
+    /// Replace the allocation log, if it is enabled, with the events that
+    /// were recorded while solving the buffer requirements of `graph_ir`.
+    fn store_allocation_log(&mut self, graph_ir: &GraphIR) {
+        if let Some(log) = &mut self.allocation_log {
+            log.clear();
+            log.extend_from_slice(graph_ir.allocation_log());
+        }
     }
 
     fn cycle_detected(&self) -> bool {
-        GraphIR::preprocess(
+        self.graph_ir().tarjan() > 0
+    }
+
+    /// The [GraphIR] to run the compiler passes on.
+    fn compile_input(&self) -> GraphIR {
+        let graph_ir = self.graph_ir();
+        if self.eliminate_dead_nodes {
+            graph_ir.eliminate_dead_nodes()
+        } else {
+            graph_ir
+        }
+    }
+
+    fn graph_ir(&self) -> GraphIR {
+        let graph_ir = GraphIR::preprocess(
             self.num_port_types,
             self.nodes.values(),
             self.edges.values(),
         )
-        .unwrap()
-        .tarjan()
-            > 0
+        .unwrap();
+        if self.sort_edges {
+            graph_ir.sort_edges()
+        } else {
+            graph_ir
+        }
     }
 }
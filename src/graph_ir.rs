@@ -7,7 +7,11 @@ use crate::{
     output_ir::*,
 };
 use fnv::{FnvHashMap, FnvHashSet};
-use std::{rc::Rc, collections::VecDeque};
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, VecDeque},
+    rc::Rc,
+};
 
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
@@ -27,6 +31,8 @@ pub struct GraphIR {
     schedule: Vec<TempEntry>,
     /// The maximum number of buffers used for each port type. Built internally.
     max_num_buffers: Vec<usize>,
+    /// The buffer allocator events, if they are being recorded. Built internally.
+    allocation_log: Option<Vec<BufferEvent>>,
 }
 
 /// An entry in the schedule order. Since it is built incrementally, it
@@ -77,6 +83,12 @@ pub struct AdjacentEdges {
     pub incoming: Vec<Edge>,
     /// The edges connected to this node's output ports.
     pub outgoing: Vec<Edge>,
+    /// The weak edges connected to this node's input ports. These are not
+    /// included in `incoming`.
+    pub weak_incoming: Vec<Edge>,
+    /// The weak edges connected to this node's output ports. These are not
+    /// included in `outgoing`.
+    pub weak_outgoing: Vec<Edge>,
 }
 
 /// Main compilation algorithm
@@ -92,6 +104,36 @@ pub fn compile<'a>(
         .merge())
 }
 
+/// Compile the graph like [compile], but first check that every input
+/// port marked as `required` has at least one incoming edge.
+pub fn compile_strict<'a>(
+    num_port_types: usize,
+    nodes: impl IntoIterator<Item = &'a Node>,
+    edges: impl IntoIterator<Item = &'a Edge>,
+) -> Result<CompiledSchedule, CompileGraphError> {
+    Ok(GraphIR::preprocess(num_port_types, nodes, edges)?
+        .validate_required_inputs()?
+        .sort_topologically()?
+        .solve_latency_requirements()
+        .solve_buffer_requirements()?
+        .merge())
+}
+
+/// Compile the graph like [compile], but order the nodes to reduce the
+/// number of buffers that are alive at the same time (see
+/// [GraphIR::sort_topologically_min_buffers]).
+pub fn compile_min_buffers<'a>(
+    num_port_types: usize,
+    nodes: impl IntoIterator<Item = &'a Node>,
+    edges: impl IntoIterator<Item = &'a Edge>,
+) -> Result<CompiledSchedule, CompileGraphError> {
+    Ok(GraphIR::preprocess(num_port_types, nodes, edges)?
+        .sort_topologically_min_buffers()?
+        .solve_latency_requirements()
+        .solve_buffer_requirements()?
+        .merge())
+}
+
 impl GraphIR {
     /// Construct a [GraphIR] instance from lists of nodes and edges, building
     /// up the adjacency table and creating an empty schedule.
@@ -121,14 +163,18 @@ impl GraphIR {
                 return Err(CompileGraphError::EdgeIDNotUnique(edge.id));
             }
 
-            let src = adjacent
-                .entry(edge.src_node)
-                .or_insert_with(AdjacentEdges::default);
-            src.outgoing.push(*edge);
-            let dst = adjacent
-                .entry(edge.dst_node)
-                .or_insert_with(AdjacentEdges::default);
-            dst.incoming.push(*edge);
+            let src = adjacent.entry(edge.src_node).or_default();
+            if edge.weak {
+                src.weak_outgoing.push(*edge);
+            } else {
+                src.outgoing.push(*edge);
+            }
+            let dst = adjacent.entry(edge.dst_node).or_default();
+            if edge.weak {
+                dst.weak_incoming.push(*edge);
+            } else {
+                dst.incoming.push(*edge);
+            }
         }
 
         Ok(Self {
@@ -137,43 +183,395 @@ impl GraphIR {
             adjacent,
             schedule: vec![],
             max_num_buffers: vec![],
+            allocation_log: None,
         })
     }
 
+    /// Sort the edges of every node by their source node and port, and then
+    /// by their destination node and port.
+    ///
+    /// The edges of a node are otherwise kept in the order they were given
+    /// to [GraphIR::preprocess], so this makes the schedule and its buffer
+    /// assignments independent of that order.
+    pub fn sort_edges(mut self) -> Self {
+        for adjacent in self.adjacent.values_mut() {
+            for edges in [
+                &mut adjacent.incoming,
+                &mut adjacent.outgoing,
+                &mut adjacent.weak_incoming,
+                &mut adjacent.weak_outgoing,
+            ] {
+                edges.sort_unstable_by_key(|e| {
+                    (e.src_node.0, e.src_port.0, e.dst_node.0, e.dst_port.0)
+                });
+            }
+        }
+        self
+    }
+
+    /// Check that every input port marked as `required` has at least one
+    /// incoming edge.
+    pub fn validate_required_inputs(self) -> Result<Self, CompileGraphError> {
+        for node in self.nodes.values() {
+            let adjacent_edges = &self.adjacent[&node.id];
+            for port in node.inputs.iter().filter(|p| p.required) {
+                if !adjacent_edges
+                    .incoming
+                    .iter()
+                    .chain(adjacent_edges.weak_incoming.iter())
+                    .any(|e| e.dst_port == port.id)
+                {
+                    return Err(CompileGraphError::UnconnectedInput(node.id, port.id));
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Check that the total latency of the graph, the latency of the
+    /// critical path, is not more than `max_latency`.
+    pub fn validate_latency_limit(self, max_latency: f64) -> Result<Self, CompileGraphError> {
+        let (total, _) = self.critical_path()?;
+        if total > max_latency {
+            return Err(CompileGraphError::LatencyBudgetExceeded {
+                total,
+                max: max_latency,
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Check that `block_size` is not zero, and that it is a multiple of the
+    /// block size required by every node.
+    // `u64::is_multiple_of()` is too new for the toolchains this crate
+    // supports.
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn validate_block_size(self, block_size: u64) -> Result<Self, CompileGraphError> {
+        if block_size == 0 {
+            return Err(CompileGraphError::ZeroBlockSize);
+        }
+
+        let mut nodes = self.nodes.values().collect::<Vec<_>>();
+        nodes.sort_unstable_by_key(|n| n.id.0);
+
+        for node in nodes {
+            if node.block_multiple > 1 && block_size % node.block_multiple != 0 {
+                return Err(CompileGraphError::IncompatibleBlockSize(node.id));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Check that the source and destination ports of every edge have the
+    /// same type.
+    ///
+    /// Edges are checked in order of their ID, and the first mismatch is
+    /// returned. Edges whose ports cannot be found are ignored.
+    pub fn validate_edge_types(self) -> Result<Self, CompileGraphError> {
+        let mut edges = self
+            .adjacent
+            .values()
+            .flat_map(|a| a.incoming.iter().chain(a.weak_incoming.iter()))
+            .collect::<Vec<_>>();
+        edges.sort_unstable_by_key(|e| e.id.0);
+
+        for edge in edges {
+            let src_port = self.nodes[&edge.src_node]
+                .outputs
+                .iter()
+                .find(|p| p.id == edge.src_port);
+            let dst_port = self.nodes[&edge.dst_node]
+                .inputs
+                .iter()
+                .find(|p| p.id == edge.dst_port);
+
+            if let (Some(src_port), Some(dst_port)) = (src_port, dst_port) {
+                if src_port.type_idx != dst_port.type_idx {
+                    return Err(CompileGraphError::EdgeTypeMismatch {
+                        edge: *edge,
+                        src_port_type: src_port.type_idx,
+                        dst_port_type: dst_port.type_idx,
+                    });
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Check that every edge goes from an output port of its source node to
+    /// an input port of its destination node.
+    ///
+    /// Edges are checked in order of their ID, and the first edge that does
+    /// not is returned. An edge whose port cannot be found counts as going
+    /// in the wrong direction.
+    pub fn validate_edge_directions(self) -> Result<Self, CompileGraphError> {
+        let mut edges = self
+            .adjacent
+            .values()
+            .flat_map(|a| a.incoming.iter().chain(a.weak_incoming.iter()))
+            .collect::<Vec<_>>();
+        edges.sort_unstable_by_key(|e| e.id.0);
+
+        for edge in edges {
+            let src_is_output = self.nodes[&edge.src_node]
+                .outputs
+                .iter()
+                .any(|p| p.id == edge.src_port);
+            let dst_is_input = self.nodes[&edge.dst_node]
+                .inputs
+                .iter()
+                .any(|p| p.id == edge.dst_port);
+
+            if !src_is_output || !dst_is_input {
+                return Err(CompileGraphError::InvalidDirection(*edge));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Remove every node that does not contribute to the output of the
+    /// graph, so that it is left out of the schedule.
+    ///
+    /// A node contributes to the output if it has no output ports (such as
+    /// a node that writes to an audio device), if it has a pinned output
+    /// port, or if one of its output ports is connected to a node that
+    /// contributes to the output.
+    pub fn eliminate_dead_nodes(self) -> Self {
+        let live = self
+            .nodes
+            .values()
+            .filter(|n| n.outputs.is_empty() || n.outputs.iter().any(|p| p.pinned))
+            .map(|n| n.id)
+            .collect::<Vec<_>>();
+
+        self.retain_ancestors_of(live)
+    }
+
+    /// Remove every node that the given node does not depend on, so that
+    /// the schedule only contains the nodes needed to produce its output.
+    ///
+    /// A node depends on the sources of all of its incoming edges
+    /// (including weak edges), and on everything they depend on. If the
+    /// node is not in the graph, every node is removed.
+    pub fn retain_ancestors(self, node_id: NodeID) -> Self {
+        let roots = if self.nodes.contains_key(&node_id) {
+            vec![node_id]
+        } else {
+            vec![]
+        };
+
+        self.retain_ancestors_of(roots)
+    }
+
+    /// Remove every node that is not one of `sources` or downstream of one
+    /// of them. Sources that are not in the graph are ignored.
+    ///
+    /// This will return an error if one of the sources has an incoming
+    /// edge, or if a node downstream of the sources has an incoming edge
+    /// from a node that is not. Weak edges from such nodes are removed
+    /// instead, since they do not constrain the schedule.
+    pub fn retain_descendants(mut self, sources: &[NodeID]) -> Result<Self, CompileGraphError> {
+        let sources = sources
+            .iter()
+            .copied()
+            .filter(|node_id| self.nodes.contains_key(node_id))
+            .collect::<Vec<_>>();
+        for node_id in sources.iter() {
+            if !self.adjacent[node_id].incoming.is_empty() {
+                return Err(CompileGraphError::SourceHasDependencies(*node_id));
+            }
+        }
+
+        let mut live: FnvHashSet<NodeID> = sources.iter().copied().collect();
+        let mut queue = VecDeque::from(sources);
+        while let Some(node_id) = queue.pop_front() {
+            let adjacent_edges = &self.adjacent[&node_id];
+            for edge in adjacent_edges
+                .outgoing
+                .iter()
+                .chain(adjacent_edges.weak_outgoing.iter())
+            {
+                if live.insert(edge.dst_node) {
+                    queue.push_back(edge.dst_node);
+                }
+            }
+        }
+
+        let mut live_nodes = live.iter().copied().collect::<Vec<_>>();
+        live_nodes.sort_unstable_by_key(|node_id| node_id.0);
+        for node_id in live_nodes {
+            for edge in self.adjacent[&node_id].incoming.iter() {
+                if !live.contains(&edge.src_node) {
+                    return Err(CompileGraphError::UnlistedSource(edge.src_node));
+                }
+            }
+        }
+
+        self.nodes.retain(|node_id, _| live.contains(node_id));
+        self.adjacent.retain(|node_id, _| live.contains(node_id));
+        for adjacent_edges in self.adjacent.values_mut() {
+            adjacent_edges
+                .weak_incoming
+                .retain(|edge| live.contains(&edge.src_node));
+        }
+
+        Ok(self)
+    }
+
+    /// Remove every node that is not one of the given nodes, or a node
+    /// that one of them depends on.
+    fn retain_ancestors_of(mut self, nodes: Vec<NodeID>) -> Self {
+        let live = self.required_nodes_for(&nodes);
+
+        self.nodes.retain(|node_id, _| live.contains(node_id));
+        self.adjacent.retain(|node_id, _| live.contains(node_id));
+        for adjacent_edges in self.adjacent.values_mut() {
+            adjacent_edges
+                .outgoing
+                .retain(|edge| live.contains(&edge.dst_node));
+            adjacent_edges
+                .weak_outgoing
+                .retain(|edge| live.contains(&edge.dst_node));
+        }
+
+        self
+    }
+
+    /// Find the given nodes and every node they depend on, the smallest set
+    /// of nodes that must be processed to produce their output.
+    ///
+    /// A node depends on the sources of all of its incoming edges
+    /// (including weak edges), and on everything they depend on. Nodes that
+    /// are not in the graph are ignored.
+    pub fn required_nodes_for(&self, nodes: &[NodeID]) -> FnvHashSet<NodeID> {
+        let nodes = nodes
+            .iter()
+            .copied()
+            .filter(|node_id| self.nodes.contains_key(node_id))
+            .collect::<Vec<_>>();
+        let mut live: FnvHashSet<NodeID> = nodes.iter().copied().collect();
+        let mut queue = VecDeque::from(nodes);
+
+        while let Some(node_id) = queue.pop_front() {
+            let adjacent_edges = &self.adjacent[&node_id];
+            for edge in adjacent_edges
+                .incoming
+                .iter()
+                .chain(adjacent_edges.weak_incoming.iter())
+            {
+                if live.insert(edge.src_node) {
+                    queue.push_back(edge.src_node);
+                }
+            }
+        }
+
+        live
+    }
+
     /// Walk the nodes of the graph and add them to the schedule.
+    ///
+    /// Every node is placed after all of the nodes it depends on. When more
+    /// than one node is ready to be scheduled, the one with the highest
+    /// `priority` goes first, and nodes with equal priority are scheduled in
+    /// the order they became ready.
     pub fn sort_topologically(mut self) -> Result<Self, CompileGraphError> {
         if self.tarjan() != 0 {
             return Err(CompileGraphError::CycleDetected);
         }
 
-        let mut queue = self.roots().cloned().collect::<VecDeque<_>>();
-        let mut visited = FnvHashSet::default();
-        visited.reserve(self.nodes.len());
+        let order = self
+            .topological_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
 
         self.schedule.clear();
+        for node_id in order {
+            self.schedule
+                .push(TempEntry::Node(self.nodes[&node_id].clone()));
+        }
 
-        while let Some(node) = queue.pop_front() {
-            if !visited.contains(&node.id) {
-                visited.insert(node.id);
-                for next in self.outgoing(&node) {
-                    queue.push_back(next.clone());
-                }
-                self.schedule.push(TempEntry::Node(node));
-            }
+        Ok(self)
+    }
+
+    /// Walk the nodes of the graph and add them to the schedule, like
+    /// [GraphIR::sort_topologically], but when more than one node is ready
+    /// to be scheduled, the one with the smallest `key` goes first instead.
+    ///
+    /// Nodes with equal keys are scheduled in the order they became ready.
+    /// `priority` is ignored, unless it is part of the key.
+    pub fn sort_topologically_by<K: Ord>(
+        mut self,
+        key: impl Fn(&Node) -> K,
+    ) -> Result<Self, CompileGraphError> {
+        if self.tarjan() != 0 {
+            return Err(CompileGraphError::CycleDetected);
+        }
+
+        let order = self
+            .topological_order_by(|node| Reverse(key(node)))
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        self.schedule.clear();
+        for node_id in order {
+            self.schedule
+                .push(TempEntry::Node(self.nodes[&node_id].clone()));
+        }
+
+        Ok(self)
+    }
+
+    /// Walk the nodes of the graph and add them to the schedule, choosing
+    /// the order of independent nodes to reduce the number of buffers that
+    /// are alive at the same time.
+    ///
+    /// Every node is still placed after all of the nodes it depends on.
+    /// When more than one node is ready to be scheduled, the one that frees
+    /// the most buffers (minus the buffers it needs for its own connected
+    /// outputs) goes first. Ties are broken by `priority` and then by the
+    /// order the nodes became ready.
+    ///
+    /// This is slower than [GraphIR::sort_topologically], since every ready
+    /// node is scored each time a node is scheduled.
+    pub fn sort_topologically_min_buffers(mut self) -> Result<Self, CompileGraphError> {
+        if self.tarjan() != 0 {
+            return Err(CompileGraphError::CycleDetected);
+        }
+
+        let order = self
+            .min_buffers_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        self.schedule.clear();
+        for node_id in order {
+            self.schedule
+                .push(TempEntry::Node(self.nodes[&node_id].clone()));
         }
 
         Ok(self)
     }
 
     pub fn solve_latency_requirements(mut self) -> Self {
-        let mut time_of_arrival = FnvHashMap::default();
-        let mut new_schedule = Vec::with_capacity(self.schedule.capacity());
+        let mut time_of_arrival =
+            FnvHashMap::with_capacity_and_hasher(self.nodes.len(), Default::default());
+        // At most one delay is inserted for every edge.
+        let mut new_schedule = Vec::with_capacity(self.schedule.len() + self.num_edges());
         for entry in self.schedule {
             let entry = entry.node(); // cast to a node
 
+            // Sidechain inputs are neither compensated nor aligned with.
             let input_latencies = self.adjacent[&entry.id]
                 .incoming
                 .iter()
+                .filter(|edge| {
+                    !entry
+                        .inputs
+                        .iter()
+                        .any(|p| p.id == edge.dst_port && p.sidechain)
+                })
                 .map(|edge| {
                     let node = edge.src_node;
                     (edge, time_of_arrival[&node])
@@ -206,10 +604,65 @@ impl GraphIR {
         self
     }
 
+    /// Record every buffer acquired and released by the next call to
+    /// [GraphIR::solve_buffer_requirements], which can be read back with
+    /// [GraphIR::allocation_log].
+    pub fn record_allocation_events(mut self) -> Self {
+        self.allocation_log = Some(vec![]);
+        self
+    }
+
+    /// The buffer allocator events recorded while solving the buffer
+    /// requirements, in order. This is empty unless
+    /// [GraphIR::record_allocation_events] was called first.
+    pub fn allocation_log(&self) -> &[BufferEvent] {
+        self.allocation_log.as_deref().unwrap_or(&[])
+    }
+
     pub fn solve_buffer_requirements(mut self) -> Result<Self, CompileGraphError> {
-        let mut new_schedule = Vec::with_capacity(self.schedule.capacity());
+        // At most one sum is inserted for every input port.
+        let num_input_ports: usize = self.nodes.values().map(|n| n.inputs.len()).sum();
+        let mut new_schedule = Vec::with_capacity(self.schedule.len() + num_input_ports);
         let mut allocator = BufferAllocator::new(self.num_port_types);
-        let mut assignment_table = FnvHashMap::default();
+        if self.allocation_log.is_some() {
+            allocator.record_events();
+        }
+        let mut assignment_table =
+            FnvHashMap::with_capacity_and_hasher(self.num_edges(), Default::default());
+        // Buffers that stay alive for the rest of the schedule, keyed by the
+        // output port they are assigned to. Holding a reference here prevents
+        // them from being released.
+        let mut persistent_buffers = FnvHashMap::default();
+
+        // The source ports of weak edges are assigned persistent buffers up front,
+        // since the destination of a weak edge may be scheduled before its source.
+        // Constant ports are assigned them up front too, so that their buffers are
+        // never used by anything else.
+        for entry in &self.schedule {
+            if let TempEntry::Node(node) = entry {
+                let weak_sources = self.adjacent[&node.id]
+                    .weak_outgoing
+                    .iter()
+                    .map(|e| e.src_port);
+                let constants = node.outputs.iter().filter(|p| p.constant).map(|p| p.id);
+                for port_id in weak_sources.chain(constants) {
+                    let port = match node.outputs.iter().find(|p| p.id == port_id) {
+                        Some(port) => port,
+                        None => continue,
+                    };
+                    if port.type_idx.0 >= self.num_port_types {
+                        return Err(CompileGraphError::PortTypeIndexOutOfBounds {
+                            node_id: node.id,
+                            port: *port,
+                            num_port_types: self.num_port_types,
+                        });
+                    }
+                    persistent_buffers
+                        .entry((node.id, port.id))
+                        .or_insert_with(|| allocator.acquire(port.type_idx));
+                }
+            }
+        }
 
         // hack to get around the borrow checker. This is sound because we do not mutate
         // the schedule internally, but keep the shared state bundled in the same data structure.
@@ -217,8 +670,12 @@ impl GraphIR {
         for entry in &self.schedule {
             match entry {
                 TempEntry::Node(node) => {
-                    let (scheduled, sums) =
-                        self.assign_node_buffers(&node, &mut allocator, &mut assignment_table)?;
+                    let (scheduled, sums) = self.assign_node_buffers(
+                        node,
+                        &mut allocator,
+                        &mut assignment_table,
+                        &mut persistent_buffers,
+                    )?;
                     for sum in sums {
                         new_schedule.push(TempEntry::Sum(sum));
                     }
@@ -234,6 +691,9 @@ impl GraphIR {
         }
 
         self.schedule = new_schedule;
+        if self.allocation_log.is_some() {
+            self.allocation_log = Some(allocator.take_events());
+        }
         self.max_num_buffers = allocator.num_buffers_per_type();
         Ok(self)
     }
@@ -244,6 +704,7 @@ impl GraphIR {
         node: &Node,
         allocator: &mut BufferAllocator,
         assignment_table: &mut FnvHashMap<EdgeID, Rc<BufferRef>>,
+        persistent_buffers: &mut FnvHashMap<(NodeID, PortID), Rc<BufferRef>>,
     ) -> Result<(ScheduledNode, impl Iterator<Item = InsertedSum>), CompileGraphError> {
         // Allocate our output data structures, any summing nodes that need to
         // be inserted, the input buffers, and the output buffers.
@@ -257,7 +718,22 @@ impl GraphIR {
         let mut buffers_to_release: Vec<Rc<BufferRef>> =
             Vec::with_capacity(node.inputs.len() + node.outputs.len());
 
-        for port in node.inputs.iter() {
+        // The inputs of a bus are assigned per type instead of per port.
+        let bus_buffers = if node.is_bus {
+            self.assign_bus_inputs(
+                node,
+                allocator,
+                assignment_table,
+                persistent_buffers,
+                &mut summing_nodes,
+                &mut input_buffers,
+                &mut buffers_to_release,
+            )?
+        } else {
+            FnvHashMap::default()
+        };
+
+        for port in node.inputs.iter().filter(|_| !node.is_bus) {
             if port.type_idx.0 >= self.num_port_types {
                 return Err(CompileGraphError::PortTypeIndexOutOfBounds {
                     node_id: node.id,
@@ -266,13 +742,32 @@ impl GraphIR {
                 });
             }
 
-            let edges = adjacent_edges
+            // The buffers of the incoming edges. Weak edges read from the persistent
+            // buffer of their source port.
+            let mut sources: Vec<(Edge, Rc<BufferRef>)> = vec![];
+            for edge in adjacent_edges
                 .incoming
                 .iter()
                 .filter(|edge| edge.dst_port == port.id)
-                .collect::<Vec<_>>();
+            {
+                let buffer = assignment_table
+                    .remove(&edge.id)
+                    .expect("No buffer assigned to edge!");
+                sources.push((*edge, buffer));
+            }
+            for edge in adjacent_edges
+                .weak_incoming
+                .iter()
+                .filter(|edge| edge.dst_port == port.id)
+            {
+                let buffer = persistent_buffers
+                    .get(&(edge.src_node, edge.src_port))
+                    .expect("No buffer assigned to weak edge!")
+                    .clone();
+                sources.push((*edge, buffer));
+            }
 
-            if edges.is_empty() {
+            if sources.is_empty() {
                 // Case 1: The port is an input and it is unconnected. Acquire a buffer, and
                 //         assign it. The buffer must be cleared. Release the buffer once the
                 //         node assignments are done.
@@ -283,19 +778,18 @@ impl GraphIR {
                     type_index: buffer.type_idx,
                     port_id: port.id,
                     should_clear: true,
+                    gain: 1.0,
                 });
                 buffers_to_release.push(buffer);
-            } else if edges.len() == 1 {
+            } else if sources.len() == 1 {
                 // Case 2: The port is an input, and has exactly one incoming edge. Lookup the
                 //         corresponding buffer and assign it. Buffer should not be cleared.
                 //         Release the buffer once the node assignments are done.
-                let buffer = assignment_table
-                    .remove(&edges[0].id)
-                    .expect("No buffer assigned to edge!");
+                let (edge, buffer) = sources.pop().unwrap();
 
                 if buffer.type_idx != port.type_idx {
                     return Err(CompileGraphError::EdgeTypeMismatch {
-                        edge: *edges[0],
+                        edge,
                         src_port_type: buffer.type_idx,
                         dst_port_type: port.type_idx,
                     });
@@ -307,6 +801,7 @@ impl GraphIR {
                     generation: buffer.generation,
                     port_id: port.id,
                     should_clear: false,
+                    gain: edge.gain,
                 });
                 buffers_to_release.push(buffer);
             } else {
@@ -320,18 +815,15 @@ impl GraphIR {
                     generation: sum_buffer.generation,
                     port_id: port.id, // only meaningful to the input port/node.
                     should_clear: false,
+                    gain: 1.0,
                 };
 
                 // The sum inputs are the corresponding output buffers of the incoming edges.
-                let mut sum_inputs: Vec<BufferAssignment> = Vec::with_capacity(edges.len());
-                for edge in edges.iter() {
-                    let buf = assignment_table
-                        .remove(&edge.id)
-                        .expect("No buffer assigned to edge!");
-
+                let mut sum_inputs: Vec<BufferAssignment> = Vec::with_capacity(sources.len());
+                for (edge, buf) in sources.drain(..) {
                     if buf.type_idx != port.type_idx {
                         return Err(CompileGraphError::EdgeTypeMismatch {
-                            edge: **edge,
+                            edge,
                             src_port_type: buf.type_idx,
                             dst_port_type: port.type_idx,
                         });
@@ -343,6 +835,7 @@ impl GraphIR {
                         generation: buf.generation,
                         port_id: edge.src_port,
                         should_clear: false,
+                        gain: edge.gain,
                     };
                     allocator.release(buf);
                     sum_inputs.push(assignment);
@@ -374,7 +867,50 @@ impl GraphIR {
                 .filter(|edge| edge.src_port == port.id)
                 .collect::<Vec<_>>();
 
-            if edges.is_empty() {
+            let persistent_buffer =
+                if let Some(buffer) = persistent_buffers.get(&(node.id, port.id)) {
+                    Some(buffer.clone())
+                } else if port.pinned {
+                    let buffer = allocator.acquire(port.type_idx);
+                    persistent_buffers.insert((node.id, port.id), buffer.clone());
+                    Some(buffer)
+                } else {
+                    None
+                };
+
+            if let Some(buffer) = persistent_buffer {
+                // Case 7: The port is a pinned or constant output, or the source of a
+                //         weak edge. Use its persistent buffer, and add it to the
+                //         assignment table with any corresponding edge IDs. The persistent
+                //         buffer is never released, keeping it alive for the rest of the
+                //         schedule.
+                for edge in &edges {
+                    assignment_table.insert(edge.id, buffer.clone());
+                }
+                output_buffers.push(BufferAssignment {
+                    buffer_index: buffer.idx,
+                    type_index: buffer.type_idx,
+                    generation: buffer.generation,
+                    port_id: port.id,
+                    should_clear: port.clear_before_write && !port.constant,
+                    gain: 1.0,
+                });
+            } else if let Some(buffer) = bus_buffers.get(&port.type_idx) {
+                // Case 8: The port is an output of a bus. Share the buffer of the bus's
+                //         inputs of the same type, and add it to the assignment table with
+                //         any corresponding edge IDs. It is released along with the inputs.
+                for edge in &edges {
+                    assignment_table.insert(edge.id, buffer.clone());
+                }
+                output_buffers.push(BufferAssignment {
+                    buffer_index: buffer.idx,
+                    type_index: buffer.type_idx,
+                    generation: buffer.generation,
+                    port_id: port.id,
+                    should_clear: false,
+                    gain: 1.0,
+                });
+            } else if edges.is_empty() {
                 // Case 5: The port is an output and it is unconnected. Acquire a buffer and
                 //         assign it. The buffer does not need to be cleared. Release the
                 //         buffer once the node assignments are done.
@@ -384,7 +920,8 @@ impl GraphIR {
                     generation: buffer.generation,
                     type_index: buffer.type_idx,
                     port_id: port.id,
-                    should_clear: false,
+                    should_clear: port.clear_before_write,
+                    gain: 1.0,
                 });
                 buffers_to_release.push(buffer);
             } else {
@@ -400,7 +937,8 @@ impl GraphIR {
                     type_index: buffer.type_idx,
                     generation: buffer.generation,
                     port_id: port.id,
-                    should_clear: false,
+                    should_clear: port.clear_before_write,
+                    gain: 1.0,
                 });
             }
         }
@@ -421,37 +959,239 @@ impl GraphIR {
         Ok((node, summing_nodes.into_iter()))
     }
 
-    pub fn assign_delay_buffers(
+    /// Assign the input buffers of a bus. All inputs of the same type share
+    /// one buffer holding the sum of every edge into them, which is returned
+    /// by type so that the outputs of the bus can share it as well.
+    #[allow(clippy::too_many_arguments)]
+    fn assign_bus_inputs(
         &self,
-        mut delay: TempDelay,
+        node: &Node,
         allocator: &mut BufferAllocator,
         assignment_table: &mut FnvHashMap<EdgeID, Rc<BufferRef>>,
-    ) -> TempDelay {
-        let input_buffer = assignment_table
-            .remove(&delay.edge.id)
-            .expect("No buffer assigned to edge!");
-        let output_buffer = allocator.acquire(input_buffer.type_idx);
-
-        delay.input_buffer = Some(BufferAssignment {
-            buffer_index: input_buffer.idx,
-            type_index: input_buffer.type_idx,
-            generation: input_buffer.generation,
-            port_id: delay.edge.src_port,
-            should_clear: false,
-        });
-
-        delay.output_buffer = Some(BufferAssignment {
-            buffer_index: output_buffer.idx,
-            type_index: output_buffer.type_idx,
-            generation: output_buffer.generation,
-            port_id: delay.edge.dst_port,
-            should_clear: false,
-        });
+        persistent_buffers: &FnvHashMap<(NodeID, PortID), Rc<BufferRef>>,
+        summing_nodes: &mut Vec<InsertedSum>,
+        input_buffers: &mut Vec<BufferAssignment>,
+        buffers_to_release: &mut Vec<Rc<BufferRef>>,
+    ) -> Result<FnvHashMap<TypeIdx, Rc<BufferRef>>, CompileGraphError> {
+        let adjacent_edges = &self.adjacent[&node.id];
+        let mut bus_buffers: FnvHashMap<TypeIdx, (Rc<BufferRef>, bool, f32)> =
+            FnvHashMap::default();
 
-        assignment_table.insert(delay.edge.id, output_buffer);
-        allocator.release(input_buffer);
-        delay
-    }
+        for port in node.inputs.iter() {
+            if port.type_idx.0 >= self.num_port_types {
+                return Err(CompileGraphError::PortTypeIndexOutOfBounds {
+                    node_id: node.id,
+                    port: *port,
+                    num_port_types: self.num_port_types,
+                });
+            }
+            if bus_buffers.contains_key(&port.type_idx) {
+                continue;
+            }
+
+            // The buffers of the incoming edges into every input of this type.
+            let ports: FnvHashSet<PortID> = node
+                .inputs
+                .iter()
+                .filter(|p| p.type_idx == port.type_idx)
+                .map(|p| p.id)
+                .collect();
+            let mut sources: Vec<(Edge, Rc<BufferRef>)> = vec![];
+            for edge in adjacent_edges
+                .incoming
+                .iter()
+                .filter(|edge| ports.contains(&edge.dst_port))
+            {
+                let buffer = assignment_table
+                    .remove(&edge.id)
+                    .expect("No buffer assigned to edge!");
+                sources.push((*edge, buffer));
+            }
+            for edge in adjacent_edges
+                .weak_incoming
+                .iter()
+                .filter(|edge| ports.contains(&edge.dst_port))
+            {
+                let buffer = persistent_buffers
+                    .get(&(edge.src_node, edge.src_port))
+                    .expect("No buffer assigned to weak edge!")
+                    .clone();
+                sources.push((*edge, buffer));
+            }
+            for (edge, buffer) in sources.iter() {
+                if buffer.type_idx != port.type_idx {
+                    return Err(CompileGraphError::EdgeTypeMismatch {
+                        edge: *edge,
+                        src_port_type: buffer.type_idx,
+                        dst_port_type: port.type_idx,
+                    });
+                }
+            }
+
+            let bus_buffer = if sources.is_empty() {
+                // Nothing is connected, so the bus starts out silent.
+                (allocator.acquire(port.type_idx), true, 1.0)
+            } else if sources.len() == 1 && Rc::strong_count(&sources[0].1) == 1 {
+                // A single source that nothing else reads from can be used
+                // in place, since the outputs of the bus may write to it.
+                let (edge, buffer) = sources.pop().unwrap();
+                (buffer, false, edge.gain)
+            } else {
+                // Otherwise sum the sources into a new buffer.
+                let sum_buffer = allocator.acquire(port.type_idx);
+                let mut sum_inputs: Vec<BufferAssignment> = Vec::with_capacity(sources.len());
+                for (edge, buf) in sources.drain(..) {
+                    sum_inputs.push(BufferAssignment {
+                        buffer_index: buf.idx,
+                        type_index: buf.type_idx,
+                        generation: buf.generation,
+                        port_id: edge.src_port,
+                        should_clear: false,
+                        gain: edge.gain,
+                    });
+                    allocator.release(buf);
+                }
+                summing_nodes.push(InsertedSum {
+                    input_buffers: sum_inputs,
+                    output_buffer: BufferAssignment {
+                        buffer_index: sum_buffer.idx,
+                        type_index: sum_buffer.type_idx,
+                        generation: sum_buffer.generation,
+                        port_id: port.id, // only meaningful to the input port/node.
+                        should_clear: false,
+                        gain: 1.0,
+                    },
+                });
+                (sum_buffer, false, 1.0)
+            };
+
+            buffers_to_release.push(bus_buffer.0.clone());
+            bus_buffers.insert(port.type_idx, bus_buffer);
+        }
+
+        for port in node.inputs.iter() {
+            let (buffer, should_clear, gain) = &bus_buffers[&port.type_idx];
+            input_buffers.push(BufferAssignment {
+                buffer_index: buffer.idx,
+                type_index: buffer.type_idx,
+                generation: buffer.generation,
+                port_id: port.id,
+                should_clear: *should_clear,
+                gain: *gain,
+            });
+        }
+
+        Ok(bus_buffers
+            .into_iter()
+            .map(|(type_idx, (buffer, _, _))| (type_idx, buffer))
+            .collect())
+    }
+
+    pub fn assign_delay_buffers(
+        &self,
+        mut delay: TempDelay,
+        allocator: &mut BufferAllocator,
+        assignment_table: &mut FnvHashMap<EdgeID, Rc<BufferRef>>,
+    ) -> TempDelay {
+        let input_buffer = assignment_table
+            .remove(&delay.edge.id)
+            .expect("No buffer assigned to edge!");
+        let output_buffer = allocator.acquire(input_buffer.type_idx);
+
+        delay.input_buffer = Some(BufferAssignment {
+            buffer_index: input_buffer.idx,
+            type_index: input_buffer.type_idx,
+            generation: input_buffer.generation,
+            port_id: delay.edge.src_port,
+            should_clear: false,
+            gain: 1.0,
+        });
+
+        delay.output_buffer = Some(BufferAssignment {
+            buffer_index: output_buffer.idx,
+            type_index: output_buffer.type_idx,
+            generation: output_buffer.generation,
+            port_id: delay.edge.dst_port,
+            should_clear: false,
+            gain: 1.0,
+        });
+
+        assignment_table.insert(delay.edge.id, output_buffer);
+        allocator.release(input_buffer);
+        delay
+    }
+
+    /// Update the latencies in a previously compiled schedule of the same
+    /// graph, without running the buffer allocation pass again.
+    ///
+    /// This must be called after the latency pass. It only succeeds if the
+    /// order of nodes and inserted delays is the same as in `schedule`, in
+    /// which case the buffer assignments are still valid. On success, this
+    /// returns the nodes whose incoming delays changed, in schedule order.
+    /// Returns `None` without modifying `schedule` otherwise.
+    pub fn update_latencies(&self, schedule: &mut CompiledSchedule) -> Option<Vec<NodeID>> {
+        let num_compiled = schedule
+            .schedule
+            .iter()
+            .filter(|entry| !matches!(entry, ScheduleEntry::Sum(_)))
+            .count();
+        if num_compiled != self.schedule.len() {
+            return None;
+        }
+
+        let compiled = schedule
+            .schedule
+            .iter()
+            .filter(|entry| !matches!(entry, ScheduleEntry::Sum(_)));
+        let same_order =
+            self.schedule
+                .iter()
+                .zip(compiled)
+                .all(|(entry, compiled)| match (entry, compiled) {
+                    (TempEntry::Node(node), ScheduleEntry::Node(scheduled)) => {
+                        node.id == scheduled.id
+                    }
+                    (TempEntry::Delay(delay), ScheduleEntry::Delay(inserted)) => {
+                        delay.edge.id == inserted.edge.id
+                    }
+                    _ => false,
+                });
+        if !same_order {
+            return None;
+        }
+
+        // Delays come right before the node they feed, so the changed nodes
+        // are found in schedule order.
+        let mut changed: Vec<NodeID> = vec![];
+        let compiled = schedule
+            .schedule
+            .iter_mut()
+            .filter(|entry| !matches!(entry, ScheduleEntry::Sum(_)));
+        for (entry, compiled) in self.schedule.iter().zip(compiled) {
+            match (entry, compiled) {
+                (TempEntry::Node(node), ScheduleEntry::Node(scheduled)) => {
+                    scheduled.latency = node.latency;
+                }
+                (TempEntry::Delay(delay), ScheduleEntry::Delay(inserted)) => {
+                    if inserted.delay != delay.delay {
+                        changed.push(inserted.edge.dst_node);
+                    }
+                    inserted.delay = delay.delay;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        schedule.delays.clear();
+        for entry in schedule.schedule.iter() {
+            if let ScheduleEntry::Delay(delay) = entry {
+                schedule.delays.push(*delay);
+            }
+        }
+
+        changed.dedup();
+        Some(changed)
+    }
 
     /// Merge the GraphIR into a [CompiledSchedule].
     ///
@@ -465,14 +1205,82 @@ impl GraphIR {
     ///     - if entry is a sum or scheduled node, add to schedule
     ///
     pub fn merge(self) -> CompiledSchedule {
+        self.merge_with_callback(|_| {})
+    }
+
+    /// Merge the GraphIR into a [CompiledSchedule] like [GraphIR::merge], calling
+    /// `f` with each entry of the schedule in order as it is produced.
+    pub fn merge_with_callback(self, mut f: impl FnMut(&ScheduleEntry)) -> CompiledSchedule {
+        let constant_ports: FnvHashSet<(NodeID, PortID)> = self
+            .nodes
+            .values()
+            .flat_map(|n| {
+                n.outputs
+                    .iter()
+                    .filter(|p| p.constant)
+                    .map(move |p| (n.id, p.id))
+            })
+            .collect();
+        let persistent_ports: FnvHashSet<(NodeID, PortID)> = self
+            .nodes
+            .values()
+            .flat_map(|n| {
+                let pinned = n.outputs.iter().filter(|p| p.pinned).map(|p| p.id);
+                let weak_sources = self.adjacent[&n.id]
+                    .weak_outgoing
+                    .iter()
+                    .map(|e| e.src_port);
+                pinned
+                    .chain(weak_sources)
+                    .map(move |port_id| (n.id, port_id))
+            })
+            .chain(constant_ports.iter().copied())
+            .collect();
+
+        let mut delays = vec![];
+        let mut constants = vec![];
+        let mut persistent = vec![];
+        let mut schedule = vec![];
+
+        let num_buffers = self.stream(|entry| {
+            f(&entry);
+            match &entry {
+                ScheduleEntry::Delay(delay) => delays.push(*delay),
+                ScheduleEntry::Node(node) => {
+                    for buffer in node.output_buffers.iter() {
+                        if constant_ports.contains(&(node.id, buffer.port_id)) {
+                            constants.push((node.id, *buffer));
+                        }
+                        if persistent_ports.contains(&(node.id, buffer.port_id)) {
+                            persistent.push((node.id, *buffer));
+                        }
+                    }
+                }
+                ScheduleEntry::Sum(_) => {}
+            }
+            schedule.push(entry);
+        });
+
+        CompiledSchedule {
+            schedule,
+            delays,
+            constants,
+            persistent,
+            num_buffers,
+        }
+    }
+
+    /// Hand each entry of the schedule to `f` in order, without collecting
+    /// them into a [CompiledSchedule].
+    ///
+    /// This returns the total number of buffers required to allocate, for
+    /// each type of port.
+    pub fn stream(self, mut f: impl FnMut(ScheduleEntry)) -> Vec<usize> {
         debug_assert!(
             self.max_num_buffers.len() == self.num_port_types,
             "Missing buffer allocations in output."
         );
 
-        let mut delays = vec![];
-        let mut schedule = vec![];
-
         for entry in self.schedule {
             let entry = match entry {
                 TempEntry::Node(_) => {
@@ -488,26 +1296,20 @@ impl GraphIR {
                         delay.output_buffer.is_some(),
                         "Unallocated output buffer in scheduled delay."
                     );
-                    let delay = InsertedDelay {
+                    ScheduleEntry::Delay(InsertedDelay {
                         edge: delay.edge,
                         delay: delay.delay,
                         input_buffer: delay.input_buffer.unwrap(),
                         output_buffer: delay.output_buffer.unwrap(),
-                    };
-                    delays.push(delay);
-                    ScheduleEntry::Delay(delay)
+                    })
                 }
                 TempEntry::ScheduledNode(node) => ScheduleEntry::Node(node),
                 TempEntry::Sum(sum) => ScheduleEntry::Sum(sum),
             };
-            schedule.push(entry);
+            f(entry);
         }
 
-        CompiledSchedule {
-            schedule,
-            delays,
-            num_buffers: self.max_num_buffers,
-        }
+        self.max_num_buffers
     }
 
     /// List the adjacent nodes along outgoing edges of `n`.
@@ -526,18 +1328,649 @@ impl GraphIR {
             .map(move |e| &self.nodes[&e.src_node])
     }
 
+    /// The total number of edges in the graph, not including weak edges.
+    fn num_edges(&self) -> usize {
+        self.adjacent.values().map(|a| a.outgoing.len()).sum()
+    }
+
+    /// The total number of entries in the adjacency lists of every node,
+    /// including weak edges. Every edge is stored once for its source and
+    /// once for its destination, so this is always twice the number of
+    /// edges.
+    #[cfg(test)]
+    pub(crate) fn raw_edge_slot_count(&self) -> usize {
+        self.adjacent
+            .values()
+            .map(|a| {
+                a.incoming.len() + a.outgoing.len() + a.weak_incoming.len() + a.weak_outgoing.len()
+            })
+            .sum()
+    }
+
     /// List root nodes, or nodes which have indegree of 0.
     pub fn roots(&self) -> impl Iterator<Item = &Node> + '_ {
         self.nodes
             .values()
-            .filter(move |n| self.incoming(*n).next().is_none())
+            .filter(move |n| self.incoming(n).next().is_none())
     }
 
     /// List the sink nodes, or nodes which have outdegree of 0.
     pub fn sinks(&self) -> impl Iterator<Item = &Node> + '_ {
         self.nodes
             .values()
-            .filter(move |n| self.outgoing(*n).next().is_none())
+            .filter(move |n| self.outgoing(n).next().is_none())
+    }
+
+    /// Find the shortest directed path from the node `from` to the node
+    /// `to` using a breadth-first search along outgoing edges, including
+    /// weak edges.
+    ///
+    /// The returned path includes both `from` and `to`. This returns `None`
+    /// if `to` is not reachable from `from`.
+    pub fn shortest_path(&self, from: NodeID, to: NodeID) -> Option<Vec<NodeID>> {
+        let mut came_from: FnvHashMap<NodeID, NodeID> = FnvHashMap::default();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        came_from.insert(from, from);
+
+        while let Some(node_id) = queue.pop_front() {
+            if node_id == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while current != from {
+                    current = came_from[&current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let adjacent = &self.adjacent[&node_id];
+            for edge in adjacent
+                .outgoing
+                .iter()
+                .chain(adjacent.weak_outgoing.iter())
+            {
+                if let Entry::Vacant(entry) = came_from.entry(edge.dst_node) {
+                    entry.insert(node_id);
+                    queue.push_back(edge.dst_node);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns `true` if data from the node `source` can reach the node
+    /// `target` along outgoing edges, including weak edges. A node always
+    /// affects itself.
+    pub fn affects(&self, source: NodeID, target: NodeID) -> bool {
+        source == target || self.reachable_from(source).contains(&target)
+    }
+
+    /// Find every simple path from `from` to `to` along outgoing edges,
+    /// including weak edges, with at most `max_depth` edges. Each path is
+    /// the list of nodes along it, starting with `from` and ending with
+    /// `to`.
+    ///
+    /// At each node the paths continue through its destinations in order
+    /// of node ID, and more than one edge to the same node counts as a
+    /// single path. If both nodes are the same, the only path is that node.
+    pub fn all_paths(&self, from: NodeID, to: NodeID, max_depth: usize) -> Vec<Vec<NodeID>> {
+        let mut paths = vec![];
+        if !self.nodes.contains_key(&from) || !self.nodes.contains_key(&to) {
+            return paths;
+        }
+
+        // Each entry of the stack holds the remaining destinations of the
+        // node at the same position in `path`.
+        let mut path = vec![from];
+        let mut stack = vec![self.destinations(from)];
+        while let Some(next) = stack.last_mut() {
+            if *path.last().unwrap() == to {
+                paths.push(path.clone());
+            } else if path.len() <= max_depth {
+                if let Some(node_id) = next.pop() {
+                    if !path.contains(&node_id) {
+                        path.push(node_id);
+                        stack.push(self.destinations(node_id));
+                    }
+                    continue;
+                }
+            }
+            path.pop();
+            stack.pop();
+        }
+
+        paths
+    }
+
+    /// The destination nodes of every outgoing edge of the given node,
+    /// including weak edges, sorted by descending ID without duplicates.
+    fn destinations(&self, node_id: NodeID) -> Vec<NodeID> {
+        let adjacent = &self.adjacent[&node_id];
+        let mut destinations = adjacent
+            .outgoing
+            .iter()
+            .chain(adjacent.weak_outgoing.iter())
+            .map(|edge| edge.dst_node)
+            .collect::<Vec<_>>();
+        destinations.sort_unstable_by_key(|id| Reverse(id.0));
+        destinations.dedup();
+        destinations
+    }
+
+    /// Find the largest total latency of the nodes along a path from `from`
+    /// to `to`, not including `from` itself. This is how much later data
+    /// from the output of `from` arrives at the output of `to`.
+    ///
+    /// Weak edges are not followed. This returns `Some(0.0)` if both nodes
+    /// are the same, `None` if there is no path between them or either node
+    /// does not exist, and an error if the graph contains a cycle.
+    pub fn path_latency(&self, from: NodeID, to: NodeID) -> Result<Option<f64>, CompileGraphError> {
+        let order = self
+            .topological_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        let mut latency: FnvHashMap<NodeID, f64> = FnvHashMap::default();
+        if self.nodes.contains_key(&from) {
+            latency.insert(from, 0.0);
+        }
+        for id in order.iter() {
+            let node_latency = match latency.get(id) {
+                Some(node_latency) => *node_latency,
+                None => continue,
+            };
+            for edge in self.adjacent[id].outgoing.iter() {
+                let path_latency = node_latency + self.nodes[&edge.dst_node].latency;
+                let entry = latency.entry(edge.dst_node).or_insert(path_latency);
+                *entry = entry.max(path_latency);
+            }
+        }
+
+        Ok(latency.get(&to).copied())
+    }
+
+    /// List every node that data from `node_id` can reach along outgoing
+    /// edges, including weak edges. The node itself is only included if it
+    /// has a weak edge back to itself.
+    pub fn descendants(&self, node_id: NodeID) -> FnvHashSet<NodeID> {
+        self.reachable_from(node_id)
+    }
+
+    /// Find the critical path through the graph, the chain of nodes
+    /// from a root to a sink with the largest total latency.
+    ///
+    /// Returns the total latency of the path and the nodes along it in
+    /// order. The path is empty if the graph has no nodes.
+    pub fn critical_path(&self) -> Result<(f64, Vec<NodeID>), CompileGraphError> {
+        let order = self
+            .topological_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        // The total latency of the longest path ending at each node, and the
+        // previous node along that path.
+        let mut longest: FnvHashMap<NodeID, (f64, Option<NodeID>)> = FnvHashMap::default();
+        for node_id in order.iter() {
+            let mut best: (f64, Option<NodeID>) = (0.0, None);
+            for edge in self.adjacent[node_id].incoming.iter() {
+                let latency = longest[&edge.src_node].0;
+                if best.1.is_none() || latency > best.0 {
+                    best = (latency, Some(edge.src_node));
+                }
+            }
+            longest.insert(*node_id, (best.0 + self.nodes[node_id].latency, best.1));
+        }
+
+        // The path always ends at a sink, even if the nodes after the one
+        // with the largest total latency add no latency of their own.
+        let mut end: Option<(NodeID, f64)> = None;
+        for node_id in order
+            .iter()
+            .filter(|node_id| self.adjacent[*node_id].outgoing.is_empty())
+        {
+            let latency = longest[node_id].0;
+            if end.map(|(_, l)| latency > l).unwrap_or(true) {
+                end = Some((*node_id, latency));
+            }
+        }
+
+        let (end, total_latency) = match end {
+            Some(end) => end,
+            None => return Ok((0.0, vec![])),
+        };
+
+        let mut path = vec![end];
+        let mut current = end;
+        while let Some(prev) = longest[&current].1 {
+            path.push(prev);
+            current = prev;
+        }
+        path.reverse();
+
+        Ok((total_latency, path))
+    }
+
+    /// Find the incoming edge of the given node that determines when its
+    /// input arrives, which is the edge whose source has the largest total
+    /// latency from the roots of the graph. Every other input of the node
+    /// is delayed to line up with this one.
+    ///
+    /// If more than one edge ties, the one with the lowest ID is returned.
+    /// This returns `None` if the node has no incoming edges or is not in
+    /// the graph, and an error if the graph contains a cycle.
+    pub fn critical_input(&self, node_id: NodeID) -> Result<Option<Edge>, CompileGraphError> {
+        let order = self
+            .topological_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        let mut time_of_arrival: FnvHashMap<NodeID, f64> =
+            FnvHashMap::with_capacity_and_hasher(order.len(), Default::default());
+        for id in order.iter() {
+            let max_input_latency = self.adjacent[id]
+                .incoming
+                .iter()
+                .fold(0.0f64, |acc, edge| acc.max(time_of_arrival[&edge.src_node]));
+            time_of_arrival.insert(*id, max_input_latency + self.nodes[id].latency);
+        }
+
+        let adjacent = match self.adjacent.get(&node_id) {
+            Some(adjacent) => adjacent,
+            None => return Ok(None),
+        };
+        let mut critical: Option<(f64, Edge)> = None;
+        for edge in adjacent.incoming.iter() {
+            let latency = time_of_arrival[&edge.src_node];
+            let is_better = match critical {
+                None => true,
+                Some((l, e)) => latency > l || (latency == l && edge.id.0 < e.id.0),
+            };
+            if is_better {
+                critical = Some((latency, *edge));
+            }
+        }
+
+        Ok(critical.map(|(_, edge)| edge))
+    }
+
+    /// Check that every node in the graph appears in the given schedule.
+    ///
+    /// This returns `CompileGraphError::IncompleteSchedule` with the lowest
+    /// ID of any node that is missing.
+    pub fn assert_complete_schedule(
+        &self,
+        schedule: &CompiledSchedule,
+    ) -> Result<(), CompileGraphError> {
+        let scheduled: FnvHashSet<NodeID> = schedule
+            .schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Node(node) => Some(node.id),
+                _ => None,
+            })
+            .collect();
+
+        let missing = self
+            .nodes
+            .keys()
+            .filter(|node_id| !scheduled.contains(node_id))
+            .min_by_key(|node_id| node_id.0);
+        match missing {
+            Some(node_id) => Err(CompileGraphError::IncompleteSchedule(*node_id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Group the nodes of the graph by their depth, which is the number of
+    /// edges along the longest path to the node from a node with no
+    /// incoming edges.
+    ///
+    /// The nodes at depth `d` are at index `d`, sorted by node ID. Weak
+    /// edges are ignored. This will return an error if the graph contains
+    /// a cycle.
+    pub fn nodes_by_depth(&self) -> Result<Vec<Vec<NodeID>>, CompileGraphError> {
+        let order = self
+            .topological_order()
+            .ok_or(CompileGraphError::CycleDetected)?;
+
+        let mut depths: FnvHashMap<NodeID, usize> =
+            FnvHashMap::with_capacity_and_hasher(order.len(), Default::default());
+        let mut levels: Vec<Vec<NodeID>> = vec![];
+        for node_id in order {
+            let depth = self.adjacent[&node_id]
+                .incoming
+                .iter()
+                .map(|edge| depths[&edge.src_node] + 1)
+                .max()
+                .unwrap_or(0);
+            depths.insert(node_id, depth);
+
+            if levels.len() <= depth {
+                levels.resize_with(depth + 1, Vec::new);
+            }
+            levels[depth].push(node_id);
+        }
+
+        for level in levels.iter_mut() {
+            level.sort_unstable_by_key(|node_id| node_id.0);
+        }
+
+        Ok(levels)
+    }
+
+    /// Find every maximal chain of two or more nodes that can be processed
+    /// as a single unit.
+    ///
+    /// Two consecutive nodes belong to the same chain when the first has
+    /// exactly one outgoing edge and the second has exactly one incoming
+    /// edge, which is that same edge. The chains are sorted by the ID of
+    /// their first node.
+    pub fn fusable_chains(&self) -> Vec<Vec<NodeID>> {
+        let next = |node_id: &NodeID| -> Option<NodeID> {
+            let outgoing = &self.adjacent[node_id].outgoing;
+            if outgoing.len() != 1 || outgoing[0].dst_node == *node_id {
+                return None;
+            }
+            let dst = outgoing[0].dst_node;
+            if self.adjacent[&dst].incoming.len() == 1 {
+                Some(dst)
+            } else {
+                None
+            }
+        };
+
+        let mut chains = vec![];
+        for node_id in self.nodes.keys() {
+            let is_start = next(node_id).is_some()
+                && !(self.adjacent[node_id].incoming.len() == 1
+                    && next(&self.adjacent[node_id].incoming[0].src_node) == Some(*node_id));
+            if !is_start {
+                continue;
+            }
+
+            let mut chain = vec![*node_id];
+            let mut current = *node_id;
+            while let Some(dst) = next(&current) {
+                chain.push(dst);
+                current = dst;
+            }
+            chains.push(chain);
+        }
+
+        chains.sort_unstable_by_key(|chain| chain[0].0);
+        chains
+    }
+
+    /// Find the connected components of the graph, treating every edge
+    /// (including weak edges) as undirected.
+    ///
+    /// Each component is sorted by node ID, and the components are sorted
+    /// by the ID of their first node.
+    pub fn connected_components(&self) -> Vec<Vec<NodeID>> {
+        let mut visited: FnvHashSet<NodeID> =
+            FnvHashSet::with_capacity_and_hasher(self.nodes.len(), Default::default());
+        let mut node_ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        node_ids.sort_unstable_by_key(|node_id| node_id.0);
+
+        let mut components = vec![];
+        for node_id in node_ids {
+            if !visited.insert(node_id) {
+                continue;
+            }
+
+            let mut component = vec![];
+            let mut stack = vec![node_id];
+            while let Some(node_id) = stack.pop() {
+                component.push(node_id);
+                let adjacent = &self.adjacent[&node_id];
+                let neighbors = adjacent
+                    .incoming
+                    .iter()
+                    .chain(adjacent.weak_incoming.iter())
+                    .map(|e| e.src_node)
+                    .chain(
+                        adjacent
+                            .outgoing
+                            .iter()
+                            .chain(adjacent.weak_outgoing.iter())
+                            .map(|e| e.dst_node),
+                    );
+                for neighbor in neighbors {
+                    if visited.insert(neighbor) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            component.sort_unstable_by_key(|node_id| node_id.0);
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Split the graph into one [GraphIR] for each of its connected
+    /// components (see [GraphIR::connected_components]), in the same order.
+    ///
+    /// Each part can be run through the rest of the compiler passes on its
+    /// own, and gets its own set of buffers.
+    pub fn split_components(self) -> Vec<GraphIR> {
+        let components = self.connected_components();
+        let Self {
+            num_port_types,
+            mut nodes,
+            mut adjacent,
+            ..
+        } = self;
+
+        components
+            .into_iter()
+            .map(|component| GraphIR {
+                num_port_types,
+                nodes: component
+                    .iter()
+                    .map(|node_id| (*node_id, nodes.remove(node_id).unwrap()))
+                    .collect(),
+                adjacent: component
+                    .iter()
+                    .map(|node_id| (*node_id, adjacent.remove(node_id).unwrap()))
+                    .collect(),
+                schedule: vec![],
+                max_num_buffers: vec![],
+                allocation_log: None,
+            })
+            .collect()
+    }
+
+    /// Find every node that can be reached from the given node along
+    /// outgoing edges, including weak edges. The node itself is only
+    /// included if it is part of a loop of weak edges.
+    fn reachable_from(&self, node_id: NodeID) -> FnvHashSet<NodeID> {
+        let mut reached: FnvHashSet<NodeID> = FnvHashSet::default();
+        let mut queue = VecDeque::new();
+        queue.push_back(node_id);
+
+        while let Some(node_id) = queue.pop_front() {
+            let adjacent = &self.adjacent[&node_id];
+            for edge in adjacent
+                .outgoing
+                .iter()
+                .chain(adjacent.weak_outgoing.iter())
+            {
+                if reached.insert(edge.dst_node) {
+                    queue.push_back(edge.dst_node);
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// Order the nodes of the graph so that every node comes after all of
+    /// the nodes it depends on, using Kahn's algorithm.
+    ///
+    /// Among the nodes that are ready, the one with the highest priority
+    /// comes first, and nodes with equal priority come in the order they
+    /// became ready. The initial roots become ready in order of node ID.
+    ///
+    /// This returns `None` if the graph contains a cycle.
+    fn topological_order(&self) -> Option<Vec<NodeID>> {
+        self.topological_order_by(|node| node.priority)
+    }
+
+    /// Order the nodes of the graph like [GraphIR::topological_order], but
+    /// among the nodes that are ready, the one with the largest `key` comes
+    /// first.
+    fn topological_order_by<K: Ord>(&self, key: impl Fn(&Node) -> K) -> Option<Vec<NodeID>> {
+        let mut in_degree: FnvHashMap<NodeID, usize> = self
+            .adjacent
+            .iter()
+            .map(|(node_id, adjacent)| (*node_id, adjacent.incoming.len()))
+            .collect();
+
+        let mut roots = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(node_id, _)| *node_id)
+            .collect::<Vec<_>>();
+        roots.sort_unstable_by_key(|node_id| node_id.0);
+
+        // The ready set, ordered by key and then by the order in which the
+        // nodes became ready.
+        let mut ready = BinaryHeap::with_capacity(self.nodes.len());
+        let mut num_ready = 0;
+        let mut push_ready = |ready: &mut BinaryHeap<(K, Reverse<usize>, u32)>, node_id: NodeID| {
+            ready.push((key(&self.nodes[&node_id]), Reverse(num_ready), node_id.0));
+            num_ready += 1;
+        };
+        for node_id in roots {
+            push_ready(&mut ready, node_id);
+        }
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some((_, _, node_id)) = ready.pop() {
+            let node_id = NodeID(node_id);
+            order.push(node_id);
+            for edge in self.adjacent[&node_id].outgoing.iter() {
+                let degree = in_degree.get_mut(&edge.dst_node).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    push_ready(&mut ready, edge.dst_node);
+                }
+            }
+        }
+
+        if order.len() == self.nodes.len() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    /// Order the nodes of the graph like [GraphIR::topological_order], but
+    /// pick the ready node that leaves the fewest buffers alive.
+    ///
+    /// This returns `None` if the graph contains a cycle.
+    fn min_buffers_order(&self) -> Option<Vec<NodeID>> {
+        let mut in_degree: FnvHashMap<NodeID, usize> = self
+            .adjacent
+            .iter()
+            .map(|(node_id, adjacent)| (*node_id, adjacent.incoming.len()))
+            .collect();
+
+        // The number of edges out of each connected output port that have
+        // not been consumed yet. The buffer of a port is alive until this
+        // reaches zero.
+        let mut remaining_uses: FnvHashMap<(NodeID, PortID), usize> = FnvHashMap::default();
+        for adjacent in self.adjacent.values() {
+            for edge in adjacent.outgoing.iter() {
+                *remaining_uses
+                    .entry((edge.src_node, edge.src_port))
+                    .or_default() += 1;
+            }
+        }
+
+        let mut roots = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(node_id, _)| *node_id)
+            .collect::<Vec<_>>();
+        roots.sort_unstable_by_key(|node_id| node_id.0);
+
+        // The ready set, in the order the nodes became ready.
+        let mut ready: Vec<NodeID> = roots;
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while !ready.is_empty() {
+            let score = |node_id: &NodeID| {
+                (
+                    self.buffer_score(*node_id, &remaining_uses),
+                    self.nodes[node_id].priority,
+                )
+            };
+            let mut best = 0;
+            let mut best_key = score(&ready[0]);
+            for (i, node_id) in ready.iter().enumerate().skip(1) {
+                let key = score(node_id);
+                // Only a strictly better key replaces the current best, so
+                // that ties go to the node that became ready first.
+                if key > best_key {
+                    best = i;
+                    best_key = key;
+                }
+            }
+
+            let node_id = ready.remove(best);
+            order.push(node_id);
+
+            for edge in self.adjacent[&node_id].incoming.iter() {
+                *remaining_uses
+                    .get_mut(&(edge.src_node, edge.src_port))
+                    .unwrap() -= 1;
+            }
+            for edge in self.adjacent[&node_id].outgoing.iter() {
+                let degree = in_degree.get_mut(&edge.dst_node).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(edge.dst_node);
+                }
+            }
+        }
+
+        if order.len() == self.nodes.len() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    /// The number of buffers that scheduling the given node next would
+    /// free, minus the number of buffers it would acquire for its
+    /// connected output ports.
+    fn buffer_score(
+        &self,
+        node_id: NodeID,
+        remaining_uses: &FnvHashMap<(NodeID, PortID), usize>,
+    ) -> isize {
+        let adjacent = &self.adjacent[&node_id];
+
+        let mut uses: FnvHashMap<(NodeID, PortID), usize> = FnvHashMap::default();
+        for edge in adjacent.incoming.iter() {
+            *uses.entry((edge.src_node, edge.src_port)).or_default() += 1;
+        }
+        let freed = uses
+            .iter()
+            .filter(|(src, count)| remaining_uses[*src] == **count)
+            .count();
+
+        let acquired = adjacent
+            .outgoing
+            .iter()
+            .map(|edge| edge.src_port)
+            .collect::<FnvHashSet<_>>()
+            .len();
+
+        freed as isize - acquired as isize
     }
 
     /// Consume the GraphIR returning a new instance with an updated schedule.
@@ -553,8 +1986,8 @@ impl GraphIR {
         let mut stack = Vec::with_capacity(self.nodes.len());
         let mut aux: FnvHashMap<NodeID, TarjanData> = self
             .nodes
-            .iter()
-            .map(|(k, _)| (*k, TarjanData::default()))
+            .keys()
+            .map(|k| (*k, TarjanData::default()))
             .collect();
 
         let mut num_cycles = 0;
@@ -596,6 +2029,7 @@ impl GraphIR {
                 let mut scc_count = 0;
                 loop {
                     if let Some(scc) = stack.pop() {
+                        aux.get_mut(&scc.id).unwrap().on_stack = false;
                         if scc.id == node.id {
                             break;
                         } else {
@@ -610,6 +2044,9 @@ impl GraphIR {
         }
 
         for (_, node) in self.nodes.iter() {
+            if aux[&node.id].index.is_some() {
+                continue;
+            }
             strong_connect(
                 self,
                 &mut aux,
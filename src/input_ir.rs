@@ -1,9 +1,15 @@
 //! Input data structures to the audio graph compiler.
 
+use std::hash::{Hash, Hasher};
+
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
 
 /// A globally unique identifier for a [Node].
+///
+/// Equality and hashing are over the raw ID value, so an ID that is
+/// reused for a new node after the old one is removed hashes the same
+/// as the old one.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NodeID(pub u32);
@@ -12,18 +18,25 @@ pub struct NodeID(pub u32);
 ///
 /// This does not need to be a globally unique identifier,
 /// just unique to the [Node] it belongs to.
+///
+/// Equality and hashing are over the raw ID value only. To key on a
+/// port across the entire graph, use a `(NodeID, PortID)` pair.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PortID(pub u32);
 
 /// A globally unique identifier for an [Edge].
+///
+/// Equality and hashing are over the raw ID value, so an ID that is
+/// reused for a new edge after the old one is removed hashes the same
+/// as the old one.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EdgeID(pub u32);
 
 /// The index of the port/buffer type.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TypeIdx(pub usize);
 
 /*
@@ -52,6 +65,22 @@ pub struct Node {
     pub outputs: Vec<Port>,
     /// The latency this node adds to data flowing through it.
     pub latency: f64,
+    /// When more than one node is ready to be scheduled, nodes with a
+    /// higher priority are scheduled first.
+    pub priority: u8,
+    /// The block size this node is processed with must be a multiple of
+    /// this, for example the FFT size of a node that processes spectra.
+    /// `0` and `1` mean the node has no requirement.
+    pub block_multiple: u64,
+    /// If `true`, then this node is a bus, such as a mixer bus. All of its
+    /// inputs of a type share one buffer holding the sum of everything
+    /// connected to them, and all of its outputs of that type share the
+    /// same buffer, so the bus is processed in place.
+    pub is_bus: bool,
+    /// The process group of this node, a hint for hosts that process the
+    /// graph on more than one thread that nodes in the same group should
+    /// run on the same thread.
+    pub group: u32,
 }
 
 /// A [Port] is a single point of input or output data
@@ -67,12 +96,43 @@ pub struct Port {
     /// A unique identifier for the type of data this port handles,
     /// for example nodes may have audio and event ports.
     pub type_idx: TypeIdx,
+    /// If `true`, then the buffer assigned to this port is kept alive
+    /// for the rest of the schedule, even if nothing is connected to it.
+    /// This is useful for ports the engine wants to read from directly,
+    /// such as meters and recording taps.
+    ///
+    /// This only has an effect on output ports.
+    pub pinned: bool,
+    /// If `true`, then this port must have at least one incoming edge
+    /// when compiling with [compile_strict](crate::compile_strict).
+    ///
+    /// This only has an effect on input ports.
+    pub required: bool,
+    /// If `true`, then this port is a constant source. It is assigned a
+    /// buffer that is not shared with any other port for the whole
+    /// schedule, which the engine fills once before processing starts.
+    ///
+    /// This only has an effect on output ports.
+    pub constant: bool,
+    /// If `true`, then this port is a sidechain input, such as the key
+    /// input of a compressor. Edges into it are not delay compensated, and
+    /// its latency is not aligned with the other inputs of the node.
+    ///
+    /// This only has an effect on input ports.
+    pub sidechain: bool,
+    /// If `true`, then the buffer of this port must be cleared before the
+    /// node is processed, because the node might not write to all of it.
+    ///
+    /// This only has an effect on output ports that are not constant.
+    pub clear_before_write: bool,
 }
 
 /// An [Edge] is a connection from source node and port to a
 /// destination node and port.
+///
+/// Equality and hashing compare the gain by its bit pattern.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug)]
 pub struct Edge {
     /// A globally unique identifier for this connection.
     pub id: EdgeID,
@@ -84,6 +144,44 @@ pub struct Edge {
     pub dst_node: NodeID,
     /// The ID of the destination port used by this edge.
     pub dst_port: PortID,
+    /// If `true`, then this edge does not constrain the order of the
+    /// schedule, and so it cannot create a cycle. The source port is
+    /// assigned a buffer that is kept alive for the whole schedule, and
+    /// the destination port reads whatever was last written to it.
+    pub weak: bool,
+    /// The gain the destination should apply when reading the source
+    /// buffer. Plain connections use a gain of `1.0`.
+    pub gain: f32,
+    /// An optional label for organizing edges, such as marking monitor
+    /// sends. This is ignored when compiling the graph.
+    pub tag: Option<u32>,
+}
+
+impl PartialEq for Edge {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.src_node == other.src_node
+            && self.src_port == other.src_port
+            && self.dst_node == other.dst_node
+            && self.dst_port == other.dst_port
+            && self.weak == other.weak
+            && self.gain.to_bits() == other.gain.to_bits()
+            && self.tag == other.tag
+    }
+}
+impl Eq for Edge {}
+
+impl Hash for Edge {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.src_node.hash(state);
+        self.src_port.hash(state);
+        self.dst_node.hash(state);
+        self.dst_port.hash(state);
+        self.weak.hash(state);
+        self.gain.to_bits().hash(state);
+        self.tag.hash(state);
+    }
 }
 
 impl From<u32> for NodeID {
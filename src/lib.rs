@@ -26,24 +26,52 @@ mod tests {
                 inputs: vec![Port {
                     id: 0.into(),
                     type_idx: 0.into(),
+                    pinned: false,
+                    required: false,
+                    constant: false,
+                    sidechain: false,
+                    clear_before_write: false,
                 }],
                 outputs: vec![Port {
                     id: 1.into(),
                     type_idx: 0.into(),
+                    pinned: false,
+                    required: false,
+                    constant: false,
+                    sidechain: false,
+                    clear_before_write: false,
                 }],
                 latency: 0.0,
+                priority: 0,
+                block_multiple: 1,
+                is_bus: false,
+                group: 0,
             },
             Node {
                 id: 1.into(),
                 inputs: vec![Port {
                     id: 0.into(),
                     type_idx: 0.into(),
+                    pinned: false,
+                    required: false,
+                    constant: false,
+                    sidechain: false,
+                    clear_before_write: false,
                 }],
                 outputs: vec![Port {
                     id: 1.into(),
                     type_idx: 0.into(),
+                    pinned: false,
+                    required: false,
+                    constant: false,
+                    sidechain: false,
+                    clear_before_write: false,
                 }],
                 latency: 0.0,
+                priority: 0,
+                block_multiple: 1,
+                is_bus: false,
+                group: 0,
             },
         ];
 
@@ -53,6 +81,9 @@ mod tests {
             src_port: nodes[0].outputs[0].id,
             dst_node: nodes[1].id,
             dst_port: nodes[1].inputs[0].id,
+            weak: false,
+            gain: 1.0,
+            tag: None,
         }];
 
         let schedule = compile(1, &nodes, &edges).unwrap();
@@ -82,6 +113,2192 @@ mod tests {
         assert_eq!(edge_src_buffer_id, edge_dst_buffer_id);
     }
 
+    #[test]
+    fn validate_edge_directions_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        assert!(graph.validate_edge_directions().is_ok());
+
+        let nodes = graph.nodes().cloned().collect::<Vec<_>>();
+        let edge = *graph.edges().next().unwrap();
+        let reversed = Edge {
+            src_node: b,
+            src_port: 0.into(),
+            dst_node: a,
+            dst_port: 1.into(),
+            ..edge
+        };
+        assert!(matches!(
+            GraphIR::preprocess(1, &nodes, &[reversed])
+                .unwrap()
+                .validate_edge_directions(),
+            Err(error::CompileGraphError::InvalidDirection(edge)) if edge.id == reversed.id
+        ));
+    }
+
+    #[test]
+    fn batch_rolls_back_on_cycle() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        for node in [a, b].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+
+        graph.begin_batch();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        graph.add_edge(b, 1.into(), a, 0.into(), true).unwrap();
+        assert!(graph.end_batch().is_err());
+        assert!(!graph.in_batch());
+        assert_eq!(graph.edges().count(), 0);
+        assert_eq!(graph.raw_edge_slot_count(), (0, 0, 0));
+
+        graph.begin_batch();
+        let edge = graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        assert!(graph.end_batch().is_ok());
+        assert_eq!(graph.edges().count(), 1);
+
+        // Edges replaced during a batch come back when it is rolled back.
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Replace);
+        graph.begin_batch();
+        let replacement = graph
+            .add_edge_with_gain(a, 1.into(), b, 0.into(), 0.5, true)
+            .unwrap();
+        graph
+            .add_edge_with_gain(a, 1.into(), b, 0.into(), 0.25, true)
+            .unwrap();
+        graph.add_edge(b, 1.into(), a, 0.into(), true).unwrap();
+        assert!(graph.edge(edge).is_none());
+        assert!(graph.end_batch().is_err());
+        assert_eq!(graph.edges().count(), 1);
+        assert!(graph.edge(replacement).is_none());
+        assert_eq!(graph.edge(edge).unwrap().gain, 1.0);
+        assert!(graph.add_edge(a, 1.into(), b, 0.into(), true).is_ok());
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Error);
+        graph.compile().unwrap();
+        assert!(!graph.needs_compile());
+
+        graph.set_cycle_check_enabled(false);
+        graph.add_edge(b, 1.into(), a, 0.into(), true).unwrap();
+        assert!(matches!(
+            graph.compile(),
+            Err(error::CompileGraphError::CycleDetected)
+        ));
+        // A failed compile leaves the graph dirty and the last schedule in place.
+        assert!(graph.needs_compile());
+        assert_eq!(graph.schedule_delta(), &[a, b]);
+    }
+
+    #[test]
+    fn pinned_output_buffer_is_not_reused() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), false).unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+
+        let buffers = |schedule: &CompiledSchedule| {
+            let mut pinned = None;
+            let mut b_out = None;
+            for entry in schedule.schedule.iter() {
+                if let ScheduleEntry::Node(node) = entry {
+                    if node.id == a {
+                        pinned = Some(node.output_buffers[0].buffer_index);
+                    } else {
+                        b_out = Some(node.output_buffers[0].buffer_index);
+                    }
+                }
+            }
+            (pinned.unwrap(), b_out.unwrap())
+        };
+
+        let (pinned, b_out) = buffers(&graph.compile().unwrap());
+        assert_eq!(pinned, b_out);
+
+        graph.set_port_pinned(a, 0.into(), true).unwrap();
+        assert!(graph.needs_compile());
+        let (pinned, b_out) = buffers(&graph.compile().unwrap());
+        assert_ne!(pinned, b_out);
+    }
+
+    #[test]
+    fn shortest_path_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..4).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // 0 -> 1 -> 2 -> 3, and a shortcut 0 -> 3.
+        for i in 0..3 {
+            graph
+                .add_edge(nodes[i], 1.into(), nodes[i + 1], 0.into(), true)
+                .unwrap();
+        }
+        graph
+            .add_edge(nodes[0], 1.into(), nodes[3], 0.into(), true)
+            .unwrap();
+
+        assert_eq!(
+            graph.shortest_path(nodes[0], nodes[3]),
+            Ok(Some(vec![nodes[0], nodes[3]]))
+        );
+        assert_eq!(
+            graph.shortest_path(nodes[1], nodes[3]),
+            Ok(Some(vec![nodes[1], nodes[2], nodes[3]]))
+        );
+        assert_eq!(graph.shortest_path(nodes[3], nodes[0]), Ok(None));
+        assert_eq!(graph.shortest_path(nodes[0], NodeID(100)), Err(()));
+
+        // Weak edges are followed too, like in `affects()`.
+        graph
+            .add_weak_edge(nodes[3], 1.into(), nodes[0], 0.into())
+            .unwrap();
+        assert_eq!(
+            graph.shortest_path(nodes[3], nodes[0]),
+            Ok(Some(vec![nodes[3], nodes[0]]))
+        );
+        assert!(graph.affects(nodes[3], nodes[0]).unwrap());
+    }
+
+    #[test]
+    fn all_paths_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = [0.0, 3.0, 1.0, 1.0]
+            .iter()
+            .map(|latency| graph.add_node(*latency))
+            .collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // 0 -> 1 -> 3, 0 -> 2 -> 3, and a weak edge 3 -> 0.
+        for (src, dst) in [(0, 1), (1, 3), (0, 2), (2, 3)].iter() {
+            graph
+                .add_edge(nodes[*src], 1.into(), nodes[*dst], 0.into(), true)
+                .unwrap();
+        }
+        graph
+            .add_weak_edge(nodes[3], 1.into(), nodes[0], 0.into())
+            .unwrap();
+
+        assert_eq!(
+            graph.all_paths(nodes[0], nodes[3], 2).unwrap(),
+            vec![
+                vec![nodes[0], nodes[1], nodes[3]],
+                vec![nodes[0], nodes[2], nodes[3]]
+            ]
+        );
+        assert_eq!(
+            graph.all_paths(nodes[1], nodes[2], 3).unwrap(),
+            vec![vec![nodes[1], nodes[3], nodes[0], nodes[2]]]
+        );
+        assert!(graph.all_paths(nodes[1], nodes[2], 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn strict_compile_rejects_unconnected_required_input() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.set_port_required(b, 0.into(), true).unwrap();
+
+        assert!(graph.compile().is_ok());
+        graph.set_node_latency(a, 1.0).unwrap();
+        assert!(matches!(
+            graph.compile_strict(),
+            Err(error::CompileGraphError::UnconnectedInput(node, port)) if node == b && port == PortID(0)
+        ));
+        assert!(graph.needs_compile());
+
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        assert!(graph.compile_strict().is_ok());
+        assert!(!graph.needs_compile());
+        assert!(graph.has_delay_compensation());
+        assert_eq!(graph.schedule_delta(), &[b]);
+
+        graph.set_node_block_requirement(b, 64).unwrap();
+        assert!(matches!(
+            graph.compile_with_block_size(100),
+            Err(error::CompileGraphError::IncompatibleBlockSize(node)) if node == b
+        ));
+        assert!(graph.needs_compile());
+        assert!(matches!(
+            graph.compile_with_block_size(0),
+            Err(error::CompileGraphError::ZeroBlockSize)
+        ));
+        let (schedule, blocks) = graph.compile_with_block_size(256).unwrap();
+        assert!(!graph.needs_compile());
+        assert_eq!(blocks, vec![(0, 1.0)]);
+        assert_eq!(schedule.delays[0].split_at_block_size(256), Some((0, 1.0)));
+
+        let mut delay = schedule.delays[0];
+        delay.delay = 600.5;
+        assert_eq!(delay.split_at_block_size(256), Some((2, 88.5)));
+        assert_eq!(delay.split_at_block_size(0), None);
+        delay.delay = -1.0;
+        assert_eq!(delay.split_at_block_size(256), None);
+    }
+
+    #[test]
+    fn remap_buffers_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), false).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        let mut schedule = graph.compile().unwrap();
+        assert_eq!(schedule.num_buffers, vec![2]);
+
+        assert!(schedule.remap_buffers(|_, _| BufferIdx(0)).is_err());
+
+        schedule
+            .remap_buffers(|idx, _| BufferIdx(10 - idx.0))
+            .unwrap();
+        assert_eq!(schedule.num_buffers, vec![11]);
+        for entry in schedule.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                for buffer in node.input_buffers.iter().chain(node.output_buffers.iter()) {
+                    assert!(buffer.buffer_index.0 == 9 || buffer.buffer_index.0 == 10);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weak_edge_feedback_loop() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        for node in [a, b].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        assert!(graph.add_edge(b, 1.into(), a, 0.into(), true).is_err());
+        graph.add_weak_edge(b, 1.into(), a, 0.into()).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        let nodes = schedule
+            .schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Node(node) => Some(node),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].id, a);
+        assert_eq!(nodes[1].id, b);
+
+        let feedback = nodes[1].output_buffers[0].buffer_index;
+        assert_eq!(nodes[0].input_buffers[0].buffer_index, feedback);
+        assert!(!nodes[0].input_buffers[0].should_clear);
+        assert_ne!(nodes[0].output_buffers[0].buffer_index, feedback);
+        assert_ne!(nodes[1].input_buffers[0].buffer_index, feedback);
+    }
+
+    #[test]
+    fn critical_path_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        assert_eq!(graph.critical_path().unwrap(), (0.0, vec![]));
+
+        let a = graph.add_node(1.0);
+        let b = graph.add_node(5.0);
+        let c = graph.add_node(2.0);
+        let d = graph.add_node(1.0);
+        for node in [a, b, c, d].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // a -> b -> d, a -> c -> d
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        let (_, latency) = graph
+            .add_edge_and_latency(a, 1.into(), c, 0.into())
+            .unwrap();
+        assert_eq!(latency, 6.0);
+        let b_to_d = graph.add_edge(b, 1.into(), d, 0.into(), true).unwrap();
+        graph.add_edge(c, 1.into(), d, 0.into(), true).unwrap();
+
+        graph.set_cycle_check_enabled(false);
+        graph.begin_batch();
+        assert!(matches!(
+            graph.add_edge_and_latency(d, 1.into(), a, 0.into()),
+            Err(error::AddEdgeError::CycleDetected)
+        ));
+        assert!(graph.end_batch().is_ok());
+        assert_eq!(graph.num_edges(), 4);
+
+        // An ignored duplicate edge in a graph that already has a cycle.
+        let d_to_a = graph.add_edge(d, 1.into(), a, 0.into(), true).unwrap();
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Ignore);
+        assert!(matches!(
+            graph.add_edge_and_latency(a, 1.into(), b, 0.into()),
+            Err(error::AddEdgeError::CycleDetected)
+        ));
+        assert_eq!(graph.num_edges(), 5);
+        graph.remove_edge(d_to_a).unwrap();
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Error);
+        graph.set_cycle_check_enabled(true);
+
+        assert_eq!(graph.critical_path().unwrap(), (7.0, vec![a, b, d]));
+        assert!(!graph.is_forest());
+        assert_eq!(
+            graph.all_paths(a, d, 2).unwrap(),
+            vec![vec![a, b, d], vec![a, c, d]]
+        );
+        assert!(graph.all_paths(a, d, 1).unwrap().is_empty());
+        assert_eq!(graph.all_paths(b, b, 0).unwrap(), vec![vec![b]]);
+        assert!(graph.all_paths(a, NodeID(99), 2).is_err());
+        assert!(!graph.is_tree_rooted_at(d).unwrap());
+        assert!(graph.is_tree_rooted_at(NodeID(99)).is_err());
+        assert_eq!(graph.critical_input(d).unwrap().unwrap().id, b_to_d);
+        assert!(graph.critical_input(a).unwrap().is_none());
+        assert!(graph.affects(a, d).unwrap());
+        assert!(!graph.affects(b, c).unwrap());
+        assert_eq!(
+            graph.descendants(a).unwrap(),
+            [b, c, d].iter().copied().collect::<FnvHashSet<_>>()
+        );
+        assert!(graph.descendants(d).unwrap().is_empty());
+        assert_eq!(graph.unique_dependencies(d).unwrap(), vec![b, c]);
+        assert_eq!(graph.unique_dependents(a).unwrap(), vec![b, c]);
+        assert!(graph.unique_dependencies(a).unwrap().is_empty());
+        assert_eq!(graph.path_latency(a, d).unwrap(), Some(6.0));
+        assert_eq!(graph.path_latency(c, d).unwrap(), Some(1.0));
+        assert_eq!(graph.path_latency(b, b).unwrap(), Some(0.0));
+        assert_eq!(graph.path_latency(b, c).unwrap(), None);
+        assert_eq!(
+            graph
+                .required_nodes_for(&[(b, 1.into()), (c, 1.into())])
+                .unwrap(),
+            [a, b, c].iter().copied().collect()
+        );
+        assert!(matches!(
+            graph.required_nodes_for(&[(d, 0.into())]),
+            Err(error::FindPortError::PortNotFound(node, _)) if node == d
+        ));
+        assert!(matches!(
+            graph.compile_with_latency_limit(6.5),
+            Err(error::CompileGraphError::LatencyBudgetExceeded { total, max })
+                if total == 7.0 && max == 6.5
+        ));
+        assert!(graph.needs_compile());
+        assert!(graph.compile_with_latency_limit(7.0).is_ok());
+        assert!(!graph.needs_compile());
+        assert!(graph.has_delay_compensation());
+
+        let mut middle = vec![b, c];
+        middle.sort_unstable_by_key(|id| id.0);
+        assert_eq!(
+            graph.nodes_by_depth().unwrap(),
+            vec![vec![a], middle, vec![d]]
+        );
+
+        assert!(matches!(
+            graph.check_edge(d, 1.into(), a, 0.into()),
+            Err(error::AddEdgeError::CycleDetected)
+        ));
+        assert!(matches!(
+            graph.check_edge(a, 1.into(), b, 0.into()),
+            Err(error::AddEdgeError::EdgeAlreadyExists(_))
+        ));
+        assert!(graph.check_edge(b, 1.into(), c, 0.into()).is_ok());
+        assert_eq!(graph.num_edges(), 4);
+    }
+
+    #[test]
+    fn path_latency_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = [0.0, 3.0, 1.0, 1.0]
+            .iter()
+            .map(|latency| graph.add_node(*latency))
+            .collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // 0 -> 1 -> 3, 0 -> 2 -> 3, and a weak edge 3 -> 0.
+        for (src, dst) in [(0, 1), (1, 3), (0, 2), (2, 3)].iter() {
+            graph
+                .add_edge(nodes[*src], 1.into(), nodes[*dst], 0.into(), true)
+                .unwrap();
+        }
+        graph
+            .add_weak_edge(nodes[3], 1.into(), nodes[0], 0.into())
+            .unwrap();
+
+        // The longer route through `1` wins, and weak edges are not followed.
+        assert_eq!(graph.path_latency(nodes[0], nodes[3]).unwrap(), Some(4.0));
+        assert_eq!(graph.path_latency(nodes[2], nodes[3]).unwrap(), Some(1.0));
+        assert_eq!(graph.path_latency(nodes[3], nodes[0]).unwrap(), None);
+        assert_eq!(graph.path_latency(nodes[0], NodeID(99)).unwrap(), None);
+    }
+
+    #[test]
+    fn nodes_with_latency_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        graph.add_node(0.0);
+        let b = graph.add_node(2.0);
+        let c = graph.add_node(0.0);
+        let d = graph.add_node(0.5);
+        assert_eq!(graph.nodes_with_latency(), vec![(b, 2.0), (d, 0.5)]);
+
+        graph.set_node_latency(b, 0.0).unwrap();
+        graph.set_node_latency(c, 1.0).unwrap();
+        graph.remove_node(d).unwrap();
+        assert_eq!(graph.nodes_with_latency(), vec![(c, 1.0)]);
+    }
+
+    #[test]
+    fn latency_only_recompile_keeps_buffers() {
+        let mut graph = AudioGraphHelper::new(1);
+        assert!(!graph.has_delay_compensation());
+        assert_eq!(graph.total_compensation_samples(), 0.0);
+        let a = graph.add_node(1.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 1.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), c, 1.into(), true).unwrap();
+
+        let first = graph.compile().unwrap();
+        assert_eq!(first.delays.len(), 1);
+        assert_eq!(first.delays[0].delay, 1.0);
+        assert!(graph.order_changed_since_last_compile());
+        assert_eq!(graph.schedule_delta().len(), 3);
+        assert!(graph.has_delay_compensation());
+
+        graph.set_node_group(c, 2).unwrap();
+        assert!(graph.needs_compile());
+        let (grouped, groups) = graph.compile_grouped().unwrap();
+        assert!(!graph.needs_compile());
+        graph.set_node_group(c, 2).unwrap();
+        assert!(!graph.needs_compile());
+        assert_eq!(groups.len(), 2);
+        assert_eq!((groups[0].0, groups[0].1.len()), (0, 2));
+        assert_eq!(groups[1].0, 2);
+        // The delay into `c` is in the same group as `c`.
+        assert!(groups[1]
+            .1
+            .iter()
+            .any(|i| matches!(grouped.schedule[*i], ScheduleEntry::Delay(_))));
+        assert!(matches!(
+            grouped.schedule[*groups[1].1.last().unwrap()],
+            ScheduleEntry::Node(ref node) if node.id == c
+        ));
+        assert_eq!(
+            graph.delay_compensations().collect::<Vec<_>>(),
+            vec![((a, 0.into()), (c, 0.into()), 1.0)]
+        );
+        assert_eq!(graph.total_compensation_samples(), 1.0);
+
+        assert!(graph
+            .set_node_latencies(&[(a, 3.0), (NodeID(100), 1.0)])
+            .is_err());
+        assert!(!graph.needs_compile());
+        graph.set_node_latencies(&[(a, 3.0), (b, 0.0)]).unwrap();
+        let second = graph.compile().unwrap();
+        assert_eq!(second.delays.len(), 1);
+        assert_eq!(second.delays[0].delay, 3.0);
+        assert!(!graph.order_changed_since_last_compile());
+        assert_eq!(graph.schedule_delta(), &[c]);
+        assert_eq!(second.delays[0].input_buffer, first.delays[0].input_buffer);
+        assert_eq!(
+            second.delays[0].output_buffer,
+            first.delays[0].output_buffer
+        );
+        assert!(second.schedule.iter().any(|entry| matches!(
+            entry,
+            ScheduleEntry::Node(node) if node.id == a && node.latency == 3.0
+        )));
+
+        graph.set_port_sidechain(c, 0.into(), true).unwrap();
+        assert!(graph.compile().unwrap().delays.is_empty());
+        assert!(!graph.has_delay_compensation());
+    }
+
+    #[test]
+    fn order_changed_between_compiles() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), false).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        graph.compile().unwrap();
+        assert!(graph.order_changed_since_last_compile());
+
+        // Nothing changed.
+        graph.compile().unwrap();
+        assert!(!graph.order_changed_since_last_compile());
+
+        graph.set_node_priority(c, 1).unwrap();
+        let schedule = graph.compile().unwrap();
+        assert!(graph.order_changed_since_last_compile());
+        assert_eq!(schedule.node_ids().collect::<Vec<_>>(), vec![c, a, b]);
+    }
+
+    #[test]
+    fn schedule_delta_lists_changed_nodes() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), false).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        let first = graph.compile().unwrap();
+        assert_eq!(graph.schedule_delta(), &[a, c, b]);
+
+        // Nothing changed.
+        graph.compile().unwrap();
+        assert!(graph.schedule_delta().is_empty());
+
+        // `c` is scheduled first, but no buffers change.
+        graph.set_node_priority(c, 1).unwrap();
+        let second = graph.compile().unwrap();
+        assert_eq!(second.changed_nodes(&first), graph.schedule_delta());
+    }
+
+    #[test]
+    fn topology_hash_test() {
+        let build = |reverse: bool| {
+            let mut graph = AudioGraphHelper::new(1);
+            let a = graph.add_node(0.0);
+            let b = graph.add_node(0.0);
+            let mut ports = vec![(a, 0, false), (a, 1, false), (b, 0, true)];
+            if reverse {
+                ports.reverse();
+            }
+            for (node, port, is_input) in ports {
+                graph
+                    .add_port(node, port.into(), 0.into(), is_input)
+                    .unwrap();
+            }
+            (graph, a, b)
+        };
+
+        let (mut graph, a, b) = build(false);
+        let (other, _, _) = build(true);
+        assert_eq!(graph.topology_hash(), other.topology_hash());
+
+        let hash = graph.topology_hash();
+        graph.set_node_latency(a, 10.0).unwrap();
+        assert_eq!(graph.topology_hash(), hash);
+
+        graph.set_node_group(a, 1).unwrap();
+        assert_eq!(graph.topology_hash(), hash);
+        graph.set_node_priority(a, 1).unwrap();
+        assert_ne!(graph.topology_hash(), hash);
+        let hash = graph.topology_hash();
+
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        assert_ne!(graph.topology_hash(), hash);
+        let hash = graph.topology_hash();
+
+        // The same edge with a gain changes the schedule.
+        let (mut other, a, b) = build(false);
+        other.set_node_priority(a, 1).unwrap();
+        other
+            .add_edge_with_gain(a, 0.into(), b, 0.into(), 0.5, true)
+            .unwrap();
+        assert_ne!(other.topology_hash(), hash);
+    }
+
+    #[test]
+    fn fusable_chains_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..5).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // 0 -> 1 -> 2 -> 4 <- 3
+        for (src, dst) in [(0, 1), (1, 2), (2, 4), (3, 4)].iter() {
+            graph
+                .add_edge(nodes[*src], 1.into(), nodes[*dst], 0.into(), true)
+                .unwrap();
+        }
+
+        assert_eq!(
+            graph.fusable_chains(),
+            vec![vec![nodes[0], nodes[1], nodes[2]]]
+        );
+    }
+
+    #[test]
+    fn dead_node_elimination() {
+        let mut graph = AudioGraphHelper::new(1);
+        let src = graph.add_node(0.0);
+        let dead = graph.add_node(0.0);
+        let out = graph.add_node(0.0);
+        graph.add_port(src, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(dead, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(dead, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(out, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(src, 0.into(), out, 0.into(), true).unwrap();
+        graph.add_edge(src, 0.into(), dead, 0.into(), true).unwrap();
+
+        let full_schedule = graph.compile().unwrap();
+        assert_eq!(full_schedule.schedule.len(), 3);
+
+        graph.set_eliminate_dead_nodes(true);
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.schedule.len(), 2);
+        assert!(schedule.schedule_position(dead).is_none());
+        assert!(graph.assert_complete_schedule(&schedule).is_ok());
+
+        let partial = graph.compile_up_to(dead).unwrap();
+        assert_eq!(partial.schedule.len(), 2);
+        assert!(partial.schedule_position(out).is_none());
+
+        graph.set_port_pinned(dead, 1.into(), true).unwrap();
+        assert!(matches!(
+            graph.assert_complete_schedule(&schedule),
+            Err(error::CompileGraphError::IncompleteSchedule(id)) if id == dead
+        ));
+        assert!(graph.assert_complete_schedule(&full_schedule).is_ok());
+        assert!(graph.compile().unwrap().schedule_position(dead).is_some());
+    }
+
+    #[test]
+    fn schedule_respects_dependencies_and_priority() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..4).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // 0 -> 1, 0 -> 2 -> 1, 3 is independent.
+        for (src, dst) in [(0, 1), (0, 2), (2, 1)].iter() {
+            graph
+                .add_edge(nodes[*src], 1.into(), nodes[*dst], 0.into(), true)
+                .unwrap();
+        }
+        graph.set_node_priority(nodes[3], 1).unwrap();
+
+        let order = graph
+            .compile()
+            .unwrap()
+            .schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Node(node) => Some(node.id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![nodes[3], nodes[0], nodes[2], nodes[1]]);
+
+        // Largest ID first, ignoring the priority of node 3.
+        let schedule = graph
+            .compile_sorted_by(|node| std::cmp::Reverse(node.id.0))
+            .unwrap();
+        assert_eq!(
+            schedule.node_ids().collect::<Vec<_>>(),
+            vec![nodes[3], nodes[0], nodes[2], nodes[1]]
+        );
+        let schedule = graph.compile_sorted_by(|node| node.id.0).unwrap();
+        assert_eq!(
+            schedule.node_ids().collect::<Vec<_>>(),
+            vec![nodes[0], nodes[2], nodes[1], nodes[3]]
+        );
+    }
+
+    #[test]
+    fn type_priority_orders_ready_nodes() {
+        let mut graph = AudioGraphHelper::new(2);
+        let audio = graph.add_node(0.0);
+        let events = graph.add_node(0.0);
+        let synth = graph.add_node(0.0);
+        graph.add_port(audio, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(events, 0.into(), 1.into(), false).unwrap();
+        graph.add_port(synth, 0.into(), 1.into(), true).unwrap();
+        graph
+            .add_edge(events, 0.into(), synth, 0.into(), true)
+            .unwrap();
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.node_ids().next(), Some(audio));
+
+        let schedule = graph.compile_with_type_priority(&[0, 1]).unwrap();
+        assert_eq!(
+            schedule.node_ids().collect::<Vec<_>>(),
+            vec![events, synth, audio]
+        );
+    }
+
+    #[test]
+    fn sums_belong_to_the_group_they_feed() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), c, 0.into(), true).unwrap();
+        graph.set_node_group(a, 1).unwrap();
+        graph.set_node_group(b, 1).unwrap();
+        graph.set_node_group(c, 3).unwrap();
+        assert!(graph.set_node_group(NodeID(9), 1).is_err());
+
+        let (schedule, groups) = graph.compile_grouped().unwrap();
+        assert_eq!(
+            groups.iter().map(|(group, _)| *group).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        let all = groups
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().copied())
+            .collect::<FnvHashSet<_>>();
+        assert_eq!(all.len(), schedule.schedule.len());
+
+        let entries = &groups[1].1;
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(
+            schedule.schedule[entries[0]],
+            ScheduleEntry::Sum(_)
+        ));
+        assert!(matches!(
+            schedule.schedule[entries[1]],
+            ScheduleEntry::Node(ref node) if node.id == c
+        ));
+    }
+
+    #[test]
+    fn edge_gain_is_surfaced_in_schedule() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 1.into(), 0.into(), true).unwrap();
+        graph
+            .add_edge_with_gain(a, 0.into(), c, 0.into(), 0.5, true)
+            .unwrap();
+        graph.add_edge(b, 0.into(), c, 0.into(), true).unwrap();
+        graph
+            .add_edge_with_gain(b, 0.into(), c, 1.into(), 0.25, true)
+            .unwrap();
+        assert!(graph.suspicious_summing().is_empty());
+
+        let schedule = graph.compile().unwrap();
+        let mut sum_gains = vec![];
+        for entry in schedule.schedule.iter() {
+            match entry {
+                ScheduleEntry::Sum(sum) => {
+                    assert_eq!(sum.output_buffer.gain, 1.0);
+                    sum_gains.extend(sum.input_buffers.iter().map(|b| b.gain));
+                }
+                ScheduleEntry::Node(node) if node.id == c => {
+                    assert_eq!(node.input_buffers[0].gain, 1.0);
+                    assert_eq!(node.input_buffers[1].gain, 0.25);
+                }
+                _ => {}
+            }
+        }
+        sum_gains.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(sum_gains, vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn tags_follow_their_edges() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        let edge = graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        assert_eq!(graph.edge_tag((a, 0.into()), (b, 0.into())), None);
+        assert_eq!(graph.edge_tag((b, 0.into()), (a, 0.into())), None);
+
+        // The existing edge is tagged instead of a new one being added.
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Ignore);
+        assert_eq!(
+            graph
+                .add_edge_tagged(a, 0.into(), b, 0.into(), 3, true)
+                .unwrap(),
+            edge
+        );
+        assert_eq!(graph.edge_tag((a, 0.into()), (b, 0.into())), Some(3));
+
+        // Tags don't change the schedule.
+        let tagged = format!("{:?}", graph.compile().unwrap().schedule);
+        assert!(!graph.needs_compile());
+
+        graph.remove_edge(edge).unwrap();
+        assert_eq!(graph.connections_with_tag(3).count(), 0);
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        assert_eq!(graph.edge_tag((a, 0.into()), (b, 0.into())), None);
+        assert_eq!(format!("{:?}", graph.compile().unwrap().schedule), tagged);
+    }
+
+    #[test]
+    fn min_buffers_schedule_uses_fewer_buffers() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let a_sink = graph.add_node(0.0);
+        let b_sink = graph.add_node(0.0);
+        for src in [a, b].iter() {
+            graph.add_port(*src, 0.into(), 0.into(), false).unwrap();
+        }
+        for sink in [a_sink, b_sink].iter() {
+            graph.add_port(*sink, 0.into(), 0.into(), true).unwrap();
+        }
+        graph.add_edge(a, 0.into(), a_sink, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), b_sink, 0.into(), true).unwrap();
+
+        assert_eq!(graph.compile().unwrap().num_buffers, vec![2]);
+
+        let schedule = graph.compile_min_buffers().unwrap();
+        assert_eq!(schedule.num_buffers, vec![1]);
+        assert!(
+            schedule.schedule_position(a).unwrap() < schedule.schedule_position(a_sink).unwrap()
+        );
+        assert!(
+            schedule.schedule_position(b).unwrap() < schedule.schedule_position(b_sink).unwrap()
+        );
+    }
+
+    #[test]
+    fn sort_topologically_min_buffers_test() {
+        let port = |id: u32| Port {
+            id: id.into(),
+            type_idx: 0.into(),
+            pinned: false,
+            required: false,
+            constant: false,
+            sidechain: false,
+            clear_before_write: false,
+        };
+        let mut nodes = (0..4)
+            .map(|id| Node {
+                id: NodeID(id),
+                inputs: vec![port(0)],
+                outputs: vec![port(1)],
+                latency: 0.0,
+                priority: 0,
+                block_multiple: 1,
+                is_bus: false,
+                group: 0,
+            })
+            .collect::<Vec<_>>();
+        let edges = |pairs: &[(u32, u32)]| {
+            pairs
+                .iter()
+                .enumerate()
+                .map(|(id, (src, dst))| Edge {
+                    id: EdgeID(id as u32),
+                    src_node: NodeID(*src),
+                    src_port: 1.into(),
+                    dst_node: NodeID(*dst),
+                    dst_port: 0.into(),
+                    weak: false,
+                    gain: 1.0,
+                    tag: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let order = |nodes: &[Node], edges: &[Edge]| {
+            GraphIR::preprocess(1, nodes, edges)
+                .unwrap()
+                .sort_topologically_min_buffers()
+                .unwrap()
+                .solve_latency_requirements()
+                .solve_buffer_requirements()
+                .unwrap()
+                .merge()
+                .node_ids()
+                .collect::<Vec<_>>()
+        };
+
+        // 0 and 2 free the same number of buffers, so the order they became
+        // ready in decides, unless a priority says otherwise.
+        let chains = edges(&[(0, 1), (2, 3)]);
+        assert_eq!(
+            order(&nodes, &chains),
+            vec![NodeID(0), NodeID(1), NodeID(2), NodeID(3)]
+        );
+        nodes[2].priority = 1;
+        assert_eq!(
+            order(&nodes, &chains),
+            vec![NodeID(2), NodeID(3), NodeID(0), NodeID(1)]
+        );
+
+        let cycle = edges(&[(0, 1), (1, 0)]);
+        assert!(matches!(
+            GraphIR::preprocess(1, &nodes, &cycle)
+                .unwrap()
+                .sort_topologically_min_buffers(),
+            Err(error::CompileGraphError::CycleDetected)
+        ));
+    }
+
+    #[test]
+    fn block_size_must_fit_every_node() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.set_node_block_requirement(a, 64).unwrap();
+        graph.set_node_block_requirement(b, 48).unwrap();
+        assert!(graph.set_node_block_requirement(NodeID(9), 2).is_err());
+        assert!(graph.set_node_block_requirement(a, 0).is_err());
+        assert_eq!(graph.node(a).unwrap().block_multiple, 64);
+
+        assert!(graph.compile_with_block_size(192).is_ok());
+        // Only `a` fails here.
+        assert!(matches!(
+            graph.compile_with_block_size(96),
+            Err(error::CompileGraphError::IncompatibleBlockSize(node)) if node == a
+        ));
+        // Both fail, and the node with the lowest ID is reported.
+        assert!(matches!(
+            graph.compile_with_block_size(100),
+            Err(error::CompileGraphError::IncompatibleBlockSize(node)) if node == a
+        ));
+
+        graph.set_node_block_requirement(a, 1).unwrap();
+        assert!(graph.compile_with_block_size(96).is_ok());
+        // Other compiles ignore block size requirements.
+        assert!(graph.compile().is_ok());
+    }
+
+    #[test]
+    fn compile_by_component_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..5).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph
+            .add_edge(nodes[3], 1.into(), nodes[0], 0.into(), true)
+            .unwrap();
+        graph
+            .add_edge(nodes[1], 1.into(), nodes[4], 0.into(), true)
+            .unwrap();
+        graph
+            .add_weak_edge(nodes[4], 1.into(), nodes[1], 0.into())
+            .unwrap();
+
+        let schedules = graph.compile_by_component().unwrap();
+        let components = schedules
+            .iter()
+            .map(|schedule| {
+                let mut ids = nodes
+                    .iter()
+                    .copied()
+                    .filter(|id| schedule.schedule_position(*id).is_some())
+                    .collect::<Vec<_>>();
+                ids.sort_unstable_by_key(|id| id.0);
+                ids
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            components,
+            vec![
+                vec![nodes[0], nodes[3]],
+                vec![nodes[1], nodes[4]],
+                vec![nodes[2]],
+            ]
+        );
+        let first = &schedules[0];
+        assert!(first.schedule_position(nodes[3]) < first.schedule_position(nodes[0]));
+    }
+
+    #[test]
+    fn components_count_buffers_from_zero() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..4).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph
+            .add_edge(nodes[0], 1.into(), nodes[1], 0.into(), true)
+            .unwrap();
+        graph
+            .add_edge(nodes[2], 1.into(), nodes[3], 0.into(), true)
+            .unwrap();
+
+        let whole = graph.compile().unwrap();
+        let schedules = graph.compile_by_component().unwrap();
+        assert_eq!(schedules.len(), 2);
+        for schedule in schedules.iter() {
+            assert_eq!(schedule.node_ids().count(), 2);
+            assert!(schedule.num_buffers[0] <= whole.num_buffers[0]);
+            for (buffer, _) in schedule.buffer_producers() {
+                assert!(buffer.buffer_index.0 < schedule.num_buffers[0]);
+            }
+        }
+        assert_eq!(schedules[1].node_ids().next(), Some(nodes[2]));
+    }
+
+    #[test]
+    fn constant_port_gets_dedicated_buffer() {
+        let mut graph = AudioGraphHelper::new(1);
+        let constant = graph.add_node(0.0);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(constant, 0.into(), 0.into(), false).unwrap();
+        graph.set_port_constant(constant, 0.into(), true).unwrap();
+        for node in [a, b].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph
+            .add_edge(constant, 0.into(), a, 0.into(), true)
+            .unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.constants.len(), 1);
+        let (node_id, constant_buffer) = schedule.constants[0];
+        assert_eq!(node_id, constant);
+        for (buffer, node_id) in schedule.buffer_producers() {
+            if node_id != constant {
+                assert_ne!(buffer.buffer_index, constant_buffer.buffer_index);
+            }
+        }
+
+        graph
+            .set_port_clear_before_write(constant, 0.into(), true)
+            .unwrap();
+        graph
+            .set_port_clear_before_write(a, 1.into(), true)
+            .unwrap();
+        let schedule = graph.compile().unwrap();
+        for (buffer, node_id) in schedule.buffer_producers() {
+            assert_eq!(buffer.should_clear, node_id == a);
+        }
+    }
+
+    #[test]
+    fn clear_before_write_only_marks_flagged_outputs() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        graph.add_edge(a, 1.into(), b, 1.into(), true).unwrap();
+
+        graph
+            .set_port_clear_before_write(a, 1.into(), true)
+            .unwrap();
+        // This has no effect on an input port.
+        graph
+            .set_port_clear_before_write(b, 0.into(), true)
+            .unwrap();
+        assert!(graph
+            .set_port_clear_before_write(a, 5.into(), true)
+            .is_err());
+
+        let schedule = graph.compile().unwrap();
+        for entry in schedule.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                for buffer in node.output_buffers.iter() {
+                    assert_eq!(buffer.should_clear, buffer.port_id == PortID(1));
+                }
+                for buffer in node.input_buffers.iter() {
+                    assert!(!buffer.should_clear);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compact_renumbers_ids() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..3).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph
+            .add_edge(nodes[0], 1.into(), nodes[1], 0.into(), true)
+            .unwrap();
+        let edge = graph
+            .add_edge(nodes[0], 1.into(), nodes[2], 0.into(), true)
+            .unwrap();
+        let touching = graph.edges_touching_node(nodes[1]).unwrap();
+        assert_eq!(touching.len(), 1);
+        assert_eq!(graph.edges_touching_node(nodes[0]).unwrap().len(), 2);
+        let mut removed = vec![];
+        graph.remove_node_tracked(nodes[1], &mut removed).unwrap();
+        assert_eq!(removed, touching);
+        assert_eq!(graph.fragmentation(), (1, 1));
+        // Removing twice must not free the same ID twice.
+        assert!(graph.remove_node(nodes[1]).is_err());
+        // IDs are not reused, so a new node does not fill the gap.
+        let new_node = graph.add_node(0.0);
+        assert!(new_node.0 > nodes[2].0);
+        assert_eq!(graph.fragmentation(), (1, 1));
+        graph.remove_node(new_node).unwrap();
+        assert_eq!(graph.fragmentation(), (2, 1));
+        graph.remove_port(nodes[0], 0.into()).unwrap();
+        assert!(matches!(
+            graph.remove_port(nodes[0], 0.into()),
+            Err(error::RemovePortError::PortNotFound(..))
+        ));
+        graph.compile().unwrap();
+
+        let (node_map, edge_map) = graph.compact();
+        assert_eq!(node_map, vec![(nodes[0], NodeID(0)), (nodes[2], NodeID(1))]);
+        assert_eq!(edge_map, vec![(edge, EdgeID(0))]);
+        assert_eq!(graph.fragmentation(), (0, 0));
+        assert!(graph.needs_compile());
+
+        let edge = graph.edge(EdgeID(0)).unwrap();
+        assert_eq!((edge.src_node, edge.dst_node), (NodeID(0), NodeID(1)));
+        assert!(graph
+            .add_edge(NodeID(0), 1.into(), NodeID(1), 0.into(), true)
+            .is_err());
+        assert_eq!(graph.add_node(0.0), NodeID(2));
+        assert!(graph.compile().is_ok());
+    }
+
+    #[test]
+    fn contains_node_and_port() {
+        let mut graph = AudioGraphHelper::new(1);
+        let removed = graph.add_node(0.0);
+        let a = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.remove_node(removed).unwrap();
+
+        assert!(graph.contains_node(a));
+        assert!(!graph.contains_node(removed));
+        assert!(graph.contains_port(a, 0.into()));
+        assert!(graph.contains_port(a, 1.into()));
+        assert!(!graph.contains_port(a, 2.into()));
+        assert!(!graph.contains_port(removed, 0.into()));
+
+        graph.remove_port(a, 0.into()).unwrap();
+        assert!(!graph.contains_port(a, 0.into()));
+
+        // The old ID of a renumbered node is stale.
+        let (node_map, _) = graph.compact();
+        assert_eq!(node_map, vec![(a, NodeID(0))]);
+        assert!(!graph.contains_node(a));
+        assert!(graph.contains_port(NodeID(0), 1.into()));
+    }
+
+    #[test]
+    fn duplicate_node_copies_everything_but_edges() {
+        let mut graph = AudioGraphHelper::new(2);
+        let a = graph.add_node(2.5);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(a, 1.into(), 1.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 1.into(), true).unwrap();
+        graph.set_port_sidechain(a, 0.into(), true).unwrap();
+        graph.set_port_pinned(a, 1.into(), true).unwrap();
+        graph.set_node_priority(a, 3).unwrap();
+        graph.set_node_block_requirement(a, 32).unwrap();
+        graph.set_node_is_bus(a, true).unwrap();
+        graph.set_node_group(a, 7).unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+
+        let copy = graph.duplicate_node(a).unwrap();
+        assert_ne!(copy, a);
+        let (original, duplicate) = (graph.node(a).unwrap(), graph.node(copy).unwrap());
+        assert_eq!(duplicate.id, copy);
+        assert_eq!(duplicate.latency, original.latency);
+        assert_eq!(duplicate.priority, original.priority);
+        assert_eq!(duplicate.block_multiple, original.block_multiple);
+        assert_eq!(duplicate.is_bus, original.is_bus);
+        assert_eq!(duplicate.group, original.group);
+        assert_eq!(
+            format!("{:?}", (&duplicate.inputs, &duplicate.outputs)),
+            format!("{:?}", (&original.inputs, &original.outputs))
+        );
+
+        assert!(graph.edges_touching_node(copy).unwrap().is_empty());
+        assert_eq!(graph.num_edges(), 1);
+        assert!(graph.duplicate_node(NodeID(99)).is_err());
+    }
+
+    #[test]
+    fn duplicate_edge_policy() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        let edge = graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        assert!(graph.add_edge(a, 0.into(), b, 0.into(), true).is_err());
+
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Ignore);
+        assert_eq!(
+            graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap(),
+            edge
+        );
+
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Replace);
+        let replaced = graph
+            .add_edge_with_gain(a, 0.into(), b, 0.into(), 0.5, true)
+            .unwrap();
+        assert_ne!(replaced, edge);
+        assert!(graph.edge(edge).is_none());
+        assert_eq!(graph.edge(replaced).unwrap().gain, 0.5);
+        assert_eq!(graph.edges().count(), 1);
+
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        assert_eq!(graph.suspicious_summing(), vec![(b, 0.into())]);
+        assert_eq!(graph.raw_edge_slot_count(), (2, 2, 4));
+
+        assert_eq!(graph.edge_tag((a, 1.into()), (b, 0.into())), None);
+        graph
+            .add_edge_tagged(a, 1.into(), b, 0.into(), 7, true)
+            .unwrap();
+        assert_eq!(graph.edge_tag((a, 1.into()), (b, 0.into())), Some(7));
+        assert_eq!(
+            graph.connections_with_tag(7).collect::<Vec<_>>(),
+            vec![((a, 1.into()), (b, 0.into()))]
+        );
+        assert_eq!(graph.connections_with_tag(8).count(), 0);
+    }
+
+    #[test]
+    fn add_edge_by_index_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 5.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 3.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 1.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 8.into(), 0.into(), true).unwrap();
+
+        assert!(matches!(
+            graph.add_edge_by_index(a, 2, b, 0, true),
+            Err(error::AddEdgeError::SrcPortIndexOutOfBounds(node, 2)) if node == a
+        ));
+        assert!(matches!(
+            graph.add_edge_by_index(a, 0, b, 1, true),
+            Err(error::AddEdgeError::DstPortIndexOutOfBounds(node, 1)) if node == b
+        ));
+        assert_eq!(graph.num_edges(), 0);
+
+        // The index counts ports in the order they were added, not by ID.
+        let edge = graph.add_edge_by_index(a, 1, b, 0, true).unwrap();
+        let edges = graph.edges_touching_node(b).unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, edge);
+        assert_eq!(
+            (edges[0].src_port, edges[0].dst_port),
+            (PortID(3), PortID(8))
+        );
+    }
+
+    #[test]
+    fn remove_port_tracked_keeps_whole_edges() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), true).unwrap();
+        let gained = graph
+            .add_edge_with_gain(a, 0.into(), b, 0.into(), 0.5, true)
+            .unwrap();
+        graph.add_weak_edge(a, 0.into(), b, 1.into()).unwrap();
+        graph.set_duplicate_edge_policy(DuplicateEdgePolicy::Ignore);
+        let tagged = graph
+            .add_edge_tagged(a, 0.into(), b, 0.into(), 4, true)
+            .unwrap();
+        assert_eq!(gained, tagged);
+        let mut expected = graph.edges_touching_node(a).unwrap();
+        expected.sort_by_key(|e| e.id.0);
+
+        let mut removed = vec![];
+        assert!(matches!(
+            graph.remove_port_tracked(a, 3.into(), &mut removed),
+            Err(error::RemovePortError::PortNotFound(node, port)) if node == a && port == PortID(3)
+        ));
+        assert!(graph
+            .remove_port_tracked(NodeID(99), 0.into(), &mut removed)
+            .is_err());
+        assert!(removed.is_empty());
+        assert_eq!(graph.num_edges(), 2);
+
+        graph
+            .remove_port_tracked(a, 0.into(), &mut removed)
+            .unwrap();
+        removed.sort_by_key(|e| e.id.0);
+        assert_eq!(removed, expected);
+        assert!(removed[0].gain == 0.5 && removed[0].tag == Some(4));
+        assert!(removed[1].weak);
+        assert_eq!(graph.num_edges(), 0);
+    }
+
+    #[test]
+    fn schedule_buffer_queries() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(2.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph
+            .add_edge_with_gain(a, 0.into(), b, 0.into(), 0.5, true)
+            .unwrap();
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(
+            schedule.live_buffers_after(0),
+            vec![(BufferIdx(0), TypeIdx(0))]
+        );
+        assert!(schedule.live_buffers_after(1).is_empty());
+        assert_eq!(
+            schedule.buffer_consumers(BufferIdx(0), TypeIdx(0)),
+            vec![(b, PortID(0))]
+        );
+        assert_eq!(
+            schedule.ports_sharing_buffer(),
+            vec![(
+                (BufferIdx(0), TypeIdx(0)),
+                vec![(a, PortID(0)), (b, PortID(0))]
+            )]
+        );
+
+        let flat = schedule.to_flat_schedule();
+        assert_eq!(flat.kinds, vec![FlatSchedule::NODE, FlatSchedule::NODE]);
+        assert_eq!(flat.ids, vec![0, 1]);
+        assert_eq!(flat.input_offsets, vec![0, 0, 1]);
+        assert_eq!(flat.inputs, vec![0]);
+        assert_eq!(flat.input_gains, vec![0.5]);
+        assert_eq!(flat.output_offsets, vec![0, 1, 1]);
+        assert_eq!(flat.outputs, vec![0]);
+        assert_eq!(flat.num_buffers, 1);
+    }
+
+    #[test]
+    fn persistent_buffers_stay_live() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        for node in [a, b, c].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph.set_port_pinned(a, 1.into(), true).unwrap();
+        graph.add_edge(a, 1.into(), b, 0.into(), true).unwrap();
+        graph.add_edge(b, 1.into(), c, 0.into(), true).unwrap();
+        graph.add_weak_edge(c, 1.into(), a, 0.into()).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        assert!(schedule.node_ids().eq([a, b, c].iter().copied()));
+        assert_eq!(
+            schedule
+                .persistent
+                .iter()
+                .map(|(node, buffer)| (*node, buffer.port_id))
+                .collect::<Vec<_>>(),
+            vec![(a, PortID(1)), (c, PortID(1))]
+        );
+        let buffer = |node: NodeID| {
+            let (_, assignment) = schedule
+                .persistent
+                .iter()
+                .find(|(n, _)| *n == node)
+                .unwrap();
+            (assignment.buffer_index, assignment.type_index)
+        };
+        let (pinned, weak_source) = (buffer(a), buffer(c));
+
+        // The weak edge is read by `a` before `c` writes it, and the pinned
+        // output of `a` is kept after `b` has read it.
+        for step in 0..3 {
+            let live = schedule.live_buffers_after(step);
+            assert!(live.contains(&pinned));
+            assert!(live.contains(&weak_source));
+        }
+        assert_eq!(schedule.live_buffers_after(2).len(), 2);
+    }
+
+    #[test]
+    fn flat_schedule_with_delays_and_sums() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(2.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), c, 0.into(), true).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        let flat = schedule.to_flat_schedule();
+        let kinds = schedule
+            .schedule
+            .iter()
+            .map(|entry| match entry {
+                ScheduleEntry::Node(_) => FlatSchedule::NODE,
+                ScheduleEntry::Delay(_) => FlatSchedule::DELAY,
+                ScheduleEntry::Sum(_) => FlatSchedule::SUM,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(flat.kinds, kinds);
+        assert!(flat.kinds.contains(&FlatSchedule::DELAY));
+        assert!(flat.kinds.contains(&FlatSchedule::SUM));
+        assert_eq!(flat.delays, vec![2.0]);
+        assert_eq!(flat.input_offsets.len(), kinds.len() + 1);
+        assert_eq!(flat.output_offsets.len(), kinds.len() + 1);
+        assert_eq!(
+            *flat.input_offsets.last().unwrap() as usize,
+            flat.inputs.len()
+        );
+        assert_eq!(flat.inputs.len(), flat.input_gains.len());
+        assert_eq!(
+            flat.num_buffers as usize,
+            schedule.num_buffers.iter().sum::<usize>()
+        );
+        for (kind, id) in flat.kinds.iter().zip(flat.ids.iter()) {
+            if *kind == FlatSchedule::SUM {
+                assert_eq!(*id, u32::MAX);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_buffer_index_offsets_by_type() {
+        let mut graph = AudioGraphHelper::new(2);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 2.into(), 1.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 2.into(), 1.into(), true).unwrap();
+        for port in 0..3 {
+            graph
+                .add_edge(a, port.into(), b, port.into(), true)
+                .unwrap();
+        }
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.num_buffers, vec![2, 1]);
+        let mut flat = vec![];
+        for (buffer, _) in schedule.buffer_producers() {
+            let index = schedule.flat_buffer_index(&buffer);
+            let offset = if buffer.type_index == TypeIdx(1) {
+                2
+            } else {
+                0
+            };
+            assert_eq!(index, offset + buffer.buffer_index.0);
+            flat.push(index);
+        }
+        flat.sort_unstable();
+        assert_eq!(flat, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn edge_validator_rejects_edges() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+
+        graph.set_edge_validator(Some(Box::new(move |_, _, _, dst_node, _| {
+            if dst_node == b {
+                Err(error::AddEdgeError::Custom("b is read-only".into()))
+            } else {
+                Ok(())
+            }
+        })));
+        assert!(matches!(
+            graph.add_edge(a, 0.into(), b, 0.into(), true),
+            Err(error::AddEdgeError::Custom(_))
+        ));
+        assert_eq!(graph.num_edges(), 0);
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+
+        graph.set_edge_validator(None);
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+    }
+
+    #[test]
+    fn shared_inputs_are_copied() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+
+        let num_buffers = graph.compile().unwrap().num_buffers[0];
+        let ops = graph.compile_with_copies().unwrap();
+        assert_eq!(ops.num_buffers, vec![num_buffers + 1]);
+
+        let mut copies = 0;
+        for (i, op) in ops.ops.iter().enumerate() {
+            if let ScheduleOp::Copy { src, dst } = op {
+                copies += 1;
+                assert_eq!(dst.buffer_index.0, num_buffers);
+                assert_ne!(src.buffer_index, dst.buffer_index);
+                match &ops.ops[i + 1] {
+                    ScheduleOp::Entry(ScheduleEntry::Node(node)) => {
+                        assert_eq!(node.input_buffers[0].buffer_index, dst.buffer_index);
+                    }
+                    _ => panic!("copy is not followed by a node"),
+                }
+            }
+        }
+        assert_eq!(copies, 2);
+    }
+
+    #[test]
+    fn allocation_log_records_events() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        graph.add_edge(b, 1.into(), c, 0.into(), true).unwrap();
+
+        assert_eq!(graph.node_inputs(b).unwrap()[0].id, PortID::from(0));
+        assert_eq!(graph.node_outputs(b).unwrap()[0].id, PortID::from(1));
+        assert!(graph.node_inputs(a).unwrap().is_empty());
+        assert!(graph.node_outputs(NodeID::from(9)).is_err());
+
+        graph.compile().unwrap();
+        assert!(graph.last_allocation_log().is_empty());
+
+        graph.set_record_allocation_events(true);
+        let schedule = graph.compile().unwrap();
+        let log = graph.last_allocation_log();
+        assert!(matches!(log.first(), Some(BufferEvent::Acquire(..))));
+
+        let mut live = FnvHashSet::default();
+        for event in log {
+            match *event {
+                BufferEvent::Acquire(idx, type_idx) => {
+                    assert!(idx.0 < schedule.num_buffers[type_idx.0]);
+                    assert!(live.insert(idx));
+                }
+                BufferEvent::Release(idx, _) => assert!(live.remove(&idx)),
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_edges_are_independent_of_insertion_order() {
+        let build = |reverse: bool| {
+            let mut graph = AudioGraphHelper::new(1);
+            graph.set_sort_edges(true);
+            let nodes = (0..4).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+            for node in nodes[..3].iter() {
+                graph.add_port(*node, 0.into(), 0.into(), false).unwrap();
+            }
+            graph.add_port(nodes[3], 0.into(), 0.into(), true).unwrap();
+            let mut srcs = nodes[..3].to_vec();
+            if reverse {
+                srcs.reverse();
+            }
+            for src in srcs {
+                graph
+                    .add_edge(src, 0.into(), nodes[3], 0.into(), true)
+                    .unwrap();
+            }
+            format!("{:?}", graph.compile().unwrap())
+        };
+
+        assert_eq!(build(false), build(true));
+    }
+
+    #[test]
+    fn from_spec_builds_graph() {
+        let audio_in = (TypeIdx(0), true);
+        let audio_out = (TypeIdx(0), false);
+        let nodes = vec![
+            (0.0, vec![audio_out]),
+            (1.0, vec![audio_in, audio_out]),
+            (0.0, vec![audio_in]),
+        ];
+
+        let (graph, ids) =
+            AudioGraphHelper::from_spec(1, &nodes, &[(0, 0, 1, 0), (1, 1, 2, 0)]).unwrap();
+        assert_eq!(ids, vec![NodeID(0), NodeID(1), NodeID(2)]);
+        assert_eq!(graph.num_edges(), 2);
+        assert_eq!(graph.node_outputs(ids[1]).unwrap()[0].id, PortID(1));
+
+        assert!(matches!(
+            AudioGraphHelper::from_spec(1, &nodes, &[(0, 0, 3, 0)]),
+            Err(error::FromSpecError::AddEdge(
+                error::AddEdgeError::DstNodeNotFound(NodeID(3))
+            ))
+        ));
+        assert!(matches!(
+            AudioGraphHelper::from_spec(1, &nodes, &[(0, 0, 1, 0), (1, 1, 0, 0)]),
+            Err(error::FromSpecError::AddEdge(_))
+        ));
+        let too_large = u32::MAX as usize + 1;
+        assert!(matches!(
+            AudioGraphHelper::from_spec(1, &nodes, &[(0, 0, too_large, 0)]),
+            Err(error::FromSpecError::IndexOutOfRange(index)) if index == too_large
+        ));
+    }
+
+    #[test]
+    fn compile_from_sources_test() {
+        let mut graph = AudioGraphHelper::new(1);
+        let hw_in = graph.add_node(0.0);
+        let fx = graph.add_node(0.0);
+        let synth = graph.add_node(0.0);
+        let orphan = graph.add_node(0.0);
+        let master = graph.add_node(0.0);
+        for node in [hw_in, fx, synth, orphan].iter() {
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        graph.add_port(fx, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(orphan, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(master, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(master, 1.into(), 0.into(), true).unwrap();
+        graph.add_edge(hw_in, 1.into(), fx, 0.into(), true).unwrap();
+        graph
+            .add_edge(fx, 1.into(), master, 0.into(), true)
+            .unwrap();
+        graph
+            .add_edge(synth, 1.into(), master, 1.into(), true)
+            .unwrap();
+
+        assert!(matches!(
+            graph.compile_from_sources(&[hw_in]),
+            Err(error::CompileGraphError::UnlistedSource(id)) if id == synth
+        ));
+        assert!(matches!(
+            graph.compile_from_sources(&[fx]),
+            Err(error::CompileGraphError::SourceHasDependencies(id)) if id == fx
+        ));
+
+        let schedule = graph.compile_from_sources(&[hw_in, synth]).unwrap();
+        assert_eq!(schedule.schedule.len(), 4);
+        assert!(schedule.schedule_position(orphan).is_none());
+    }
+
+    #[test]
+    fn converted_edge_inserts_converter_node() {
+        let mut graph = AudioGraphHelper::new(2);
+        let control = graph.add_node(0.0);
+        let osc = graph.add_node(0.0);
+        graph.add_port(control, 0.into(), 1.into(), false).unwrap();
+        graph.add_port(osc, 0.into(), 0.into(), true).unwrap();
+
+        assert!(matches!(
+            graph.add_converted_edge(control, 0.into(), osc, 0.into(), true),
+            Err(error::AddEdgeError::NoTypeConverter { from, to }) if from == TypeIdx(1) && to == TypeIdx(0)
+        ));
+        assert_eq!(graph.nodes().count(), 2);
+
+        graph.register_type_converter(1.into(), 0.into(), 0.0);
+        let (converter, _, _) = graph
+            .add_converted_edge(control, 0.into(), osc, 0.into(), true)
+            .unwrap();
+        assert_eq!(graph.num_edges(), 2);
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(
+            schedule.node_ids().collect::<Vec<_>>(),
+            vec![control, converter, osc]
+        );
+        assert_eq!(schedule.num_buffers, vec![1, 1]);
+
+        // A converted edge that is rejected leaves the graph clean.
+        graph.set_edge_validator(Some(Box::new(move |_, _, _, dst_node, _| {
+            if dst_node == osc {
+                Err(error::AddEdgeError::Custom("osc is full".into()))
+            } else {
+                Ok(())
+            }
+        })));
+        assert!(graph
+            .add_converted_edge(control, 0.into(), osc, 0.into(), true)
+            .is_err());
+        assert_eq!((graph.nodes().count(), graph.num_edges()), (3, 2));
+        assert!(!graph.needs_compile());
+    }
+
+    #[test]
+    fn edge_type_test() {
+        let mut graph = AudioGraphHelper::new(2);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(a, 1.into(), 1.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(b, 1.into(), 1.into(), true).unwrap();
+        let audio = graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        let control = graph.add_edge(a, 1.into(), b, 1.into(), true).unwrap();
+
+        assert_eq!(graph.edge_type(audio), Some(TypeIdx(0)));
+        assert_eq!(graph.edge_type(control), Some(TypeIdx(1)));
+        graph.remove_edge(control).unwrap();
+        assert_eq!(graph.edge_type(control), None);
+        assert_eq!(graph.edge_type(EdgeID(99)), None);
+    }
+
+    #[test]
+    fn empty_graph_compiles() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(1.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        graph.compile().unwrap();
+        graph.remove_node(a).unwrap();
+        graph.remove_node(b).unwrap();
+
+        for schedule in [
+            graph.compile().unwrap(),
+            graph.compile_strict().unwrap(),
+            graph.compile_min_buffers().unwrap(),
+            graph.compile_from_sources(&[]).unwrap(),
+        ]
+        .iter()
+        {
+            assert!(schedule.schedule.is_empty());
+            assert!(schedule.delays.is_empty());
+            assert_eq!(schedule.num_buffers, vec![0]);
+            assert!(schedule.live_buffers_after(0).is_empty());
+            assert!(schedule.binding_table().is_empty());
+            assert!(schedule.with_copies().ops.is_empty());
+            assert_eq!(schedule.to_flat_schedule().input_offsets, vec![0]);
+            assert!(graph.assert_complete_schedule(schedule).is_ok());
+        }
+        assert!(graph.compile_by_component().unwrap().is_empty());
+        assert_eq!(graph.critical_path().unwrap(), (0.0, vec![]));
+        assert!(graph.nodes_by_depth().unwrap().is_empty());
+        assert!(graph.fusable_chains().is_empty());
+        assert!(graph.suspicious_summing().is_empty());
+        assert_eq!(graph.max_fan_out(), 0);
+        assert_eq!(graph.max_fan_in_node(), None);
+        assert_eq!(graph.num_edges(), 0);
+        assert_eq!(graph.fragmentation(), (2, 1));
+
+        let mut empty = AudioGraphHelper::new(1);
+        assert_eq!(empty.topology_hash(), graph.topology_hash());
+        assert!(empty.compile().unwrap().schedule.is_empty());
+    }
+
+    #[test]
+    fn bus_shares_one_buffer() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let bus = graph.add_node(0.0);
+        let master = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(bus, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(bus, 1.into(), 0.into(), true).unwrap();
+        graph.add_port(bus, 2.into(), 0.into(), false).unwrap();
+        graph.add_port(master, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), bus, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), bus, 1.into(), true).unwrap();
+        graph
+            .add_edge(bus, 2.into(), master, 0.into(), true)
+            .unwrap();
+        assert!(graph.set_node_is_bus(NodeID(99), true).is_err());
+        assert!(graph.is_forest());
+        assert_eq!(graph.max_fan_in_node(), Some((bus, 2)));
+        assert!(graph.is_tree_rooted_at(master).unwrap());
+        assert!(!graph.is_tree_rooted_at(bus).unwrap());
+        graph.set_node_is_bus(bus, true).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        let sums: Vec<&InsertedSum> = schedule
+            .schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Sum(sum) => Some(sum),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sums.len(), 1);
+        assert_eq!(sums[0].input_buffers.len(), 2);
+
+        let bus_buffer = sums[0].output_buffer.buffer_index;
+        let find = |id: NodeID| {
+            schedule
+                .schedule
+                .iter()
+                .find_map(|entry| match entry {
+                    ScheduleEntry::Node(node) if node.id == id => Some(node),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let scheduled_bus = find(bus);
+        assert!(scheduled_bus
+            .input_buffers
+            .iter()
+            .chain(scheduled_bus.output_buffers.iter())
+            .all(|b| b.buffer_index == bus_buffer));
+        assert_eq!(find(master).input_buffers[0].buffer_index, bus_buffer);
+
+        // A single source that nothing else reads is processed in place.
+        graph.remove_node(b).unwrap();
+        let schedule = graph.compile().unwrap();
+        assert_eq!(schedule.num_buffers, vec![1]);
+
+        let bus_to_master = graph.edges_touching_node(master).unwrap()[0];
+        assert!(matches!(
+            graph.solo_output((master, 0.into()), (a, 0.into())),
+            Err(error::AddEdgeError::SrcPortNotFound(..))
+        ));
+        assert_eq!(graph.num_edges(), 2);
+        let token = graph
+            .solo_output((a, 0.into()), (master, 0.into()))
+            .unwrap();
+        let soloed = graph.edges_touching_node(master).unwrap();
+        assert_eq!((soloed.len(), soloed[0].src_node), (1, a));
+        assert!(graph.unsolo(token).is_empty());
+        let restored = graph.edges_touching_node(master).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            (restored[0].src_node, restored[0].src_port),
+            (bus_to_master.src_node, bus_to_master.src_port)
+        );
+    }
+
+    #[test]
+    fn unsolo_after_compact() {
+        let mut graph = AudioGraphHelper::new(1);
+        let removed = graph.add_node(0.0);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        let master = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(master, 0.into(), 0.into(), true).unwrap();
+        graph
+            .add_edge_with_gain(b, 0.into(), master, 0.into(), 0.5, true)
+            .unwrap();
+        graph.remove_node(removed).unwrap();
+
+        let mut token = graph
+            .solo_output((a, 0.into()), (master, 0.into()))
+            .unwrap();
+        let (node_map, edge_map) = graph.compact();
+        token.remap(&node_map, &edge_map);
+        let new_id = |old: NodeID| node_map.iter().find(|(o, _)| *o == old).unwrap().1;
+        let (a, b, master) = (new_id(a), new_id(b), new_id(master));
+
+        assert!(graph.unsolo(token).is_empty());
+        let restored = graph.edges_touching_node(master).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!((restored[0].src_node, restored[0].gain), (b, 0.5));
+        assert!(graph.edges_touching_node(a).unwrap().is_empty());
+        assert!(graph.compile().is_ok());
+
+        // An edge the host added between the soloed ports is left alone.
+        let token = graph
+            .solo_output((a, 0.into()), (master, 0.into()))
+            .unwrap();
+        let solo_edge = graph.edges_touching_node(a).unwrap()[0].id;
+        graph.remove_edge(solo_edge).unwrap();
+        let host_edge = graph.add_edge(a, 0.into(), master, 0.into(), true).unwrap();
+        let skipped = graph.unsolo(token);
+        assert!(skipped.is_empty());
+        assert!(graph.edge(host_edge).is_some());
+        assert_eq!(graph.edges_touching_node(master).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn output_buffers_follow_port_order() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(0.0);
+        let b = graph.add_node(0.0);
+        for port in [2, 0, 1].iter() {
+            graph.add_port(a, (*port).into(), 0.into(), false).unwrap();
+        }
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        let scheduled = match &schedule.schedule[0] {
+            ScheduleEntry::Node(node) if node.id == a => node,
+            _ => panic!("first entry not node a"),
+        };
+        assert!(scheduled.output_buffers.iter().map(|b| b.port_id).eq(graph
+            .node_outputs(a)
+            .unwrap()
+            .iter()
+            .map(|p| p.id)));
+
+        let buffer = scheduled.output_buffer(0.into()).unwrap();
+        assert_eq!(buffer, scheduled.output_buffers[1]);
+        assert_eq!(
+            schedule.buffer_consumers(buffer.buffer_index, buffer.type_index),
+            vec![(b, PortID(0))]
+        );
+        assert!(scheduled.output_buffer(5.into()).is_none());
+    }
+
+    #[test]
+    fn tarjan_counts_only_real_cycles() {
+        let port = |id: u32| Port {
+            id: id.into(),
+            type_idx: 0.into(),
+            pinned: false,
+            required: false,
+            constant: false,
+            sidechain: false,
+            clear_before_write: false,
+        };
+        let nodes = (0..6)
+            .map(|id| Node {
+                id: NodeID(id),
+                inputs: vec![port(0)],
+                outputs: vec![port(1)],
+                latency: 0.0,
+                priority: 0,
+                block_multiple: 1,
+                is_bus: false,
+                group: 0,
+            })
+            .collect::<Vec<_>>();
+        let edges = |pairs: &[(u32, u32)]| {
+            pairs
+                .iter()
+                .enumerate()
+                .map(|(id, (src, dst))| Edge {
+                    id: EdgeID(id as u32),
+                    src_node: NodeID(*src),
+                    src_port: 1.into(),
+                    dst_node: NodeID(*dst),
+                    dst_port: 0.into(),
+                    weak: false,
+                    gain: 1.0,
+                    tag: None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Every node is reached more than once, from different roots.
+        let diamonds = edges(&[(0, 2), (1, 2), (2, 3), (2, 4), (3, 5), (4, 5), (0, 5)]);
+        let graph_ir = GraphIR::preprocess(1, &nodes, &diamonds).unwrap();
+        assert_eq!(graph_ir.tarjan(), 0);
+
+        let two_cycles = edges(&[(0, 1), (1, 0), (1, 2), (2, 3), (3, 4), (4, 2), (5, 3)]);
+        let graph_ir = GraphIR::preprocess(1, &nodes, &two_cycles).unwrap();
+        assert_eq!(graph_ir.tarjan(), 2);
+    }
+
+    #[test]
+    fn topological_order_is_pinned() {
+        let mut graph = AudioGraphHelper::new(1);
+        let nodes = (0..5).map(|_| graph.add_node(0.0)).collect::<Vec<_>>();
+        for node in nodes.iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), false).unwrap();
+        }
+        // The edge into 3 from 0 is added before the path through 1, so a
+        // breadth-first walk would reach 3 before 1.
+        for (src, dst) in [(0, 3), (0, 1), (1, 3), (2, 4)].iter() {
+            graph
+                .add_edge(nodes[*src], 1.into(), nodes[*dst], 0.into(), true)
+                .unwrap();
+        }
+
+        // Roots in order of ID, then nodes in the order they became ready.
+        let schedule = graph.compile().unwrap();
+        assert_eq!(
+            schedule.node_ids().collect::<Vec<_>>(),
+            vec![nodes[0], nodes[2], nodes[1], nodes[4], nodes[3]]
+        );
+    }
+
+    #[test]
+    fn compile_variants_share_the_cache() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(1.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+        graph.set_record_allocation_events(true);
+
+        assert!(graph.compile_min_buffers().is_ok());
+        assert!(!graph.needs_compile());
+        assert!(graph.order_changed_since_last_compile());
+        assert_eq!(graph.schedule_delta(), &[a, b]);
+        assert!(!graph.last_allocation_log().is_empty());
+
+        // Only the delay into `b` changed, so the cached schedule is reused
+        // and the callback still sees every entry.
+        graph.set_node_latency(a, 2.0).unwrap();
+        let mut entries = 0;
+        let schedule = graph.compile_with_callback(|_| entries += 1).unwrap();
+        assert_eq!(entries, schedule.schedule.len());
+        assert!(!graph.order_changed_since_last_compile());
+        assert_eq!(graph.schedule_delta(), &[b]);
+
+        graph.set_node_latency(a, 1.0).unwrap();
+        assert_eq!(graph.compile_by_component().unwrap().len(), 1);
+        assert!(graph.needs_compile());
+
+        let mut streamed = vec![];
+        let num_buffers = graph.compile_stream(|entry| streamed.push(entry)).unwrap();
+        assert_eq!(num_buffers, schedule.num_buffers);
+        assert_eq!(streamed.len(), schedule.schedule.len());
+        assert!(!graph.needs_compile());
+
+        graph.set_node_latency(a, 3.0).unwrap();
+        assert!(matches!(
+            graph.compile_from_sources(&[b]),
+            Err(error::CompileGraphError::SourceHasDependencies(node)) if node == b
+        ));
+        assert!(graph.needs_compile());
+        assert!(graph.compile_from_sources(&[a]).is_ok());
+        assert!(!graph.needs_compile());
+        assert_eq!(graph.schedule_delta(), &[b]);
+    }
+
+    #[test]
+    fn critical_path_ends_at_a_sink() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(5.0);
+        let b = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        assert_eq!(graph.critical_path().unwrap(), (5.0, vec![a, b]));
+    }
+
+    #[test]
+    fn latency_limit_includes_the_critical_path() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(2.0);
+        let b = graph.add_node(3.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), b, 0.into(), true).unwrap();
+
+        assert!(graph.compile_with_latency_limit(5.0).is_ok());
+        graph.set_node_latency(b, 3.5).unwrap();
+        assert!(matches!(
+            graph.compile_with_latency_limit(5.0),
+            Err(error::CompileGraphError::LatencyBudgetExceeded { total, max })
+                if total == 5.5 && max == 5.0
+        ));
+        assert!(graph.needs_compile());
+        assert!(graph.compile_with_latency_limit(f64::INFINITY).is_ok());
+    }
+
+    #[test]
+    fn sidechain_input_is_not_compensated() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(4.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        let d = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        for node in [c, d].iter() {
+            graph.add_port(*node, 0.into(), 0.into(), true).unwrap();
+            graph.add_port(*node, 1.into(), 0.into(), true).unwrap();
+        }
+        graph.add_port(c, 2.into(), 0.into(), false).unwrap();
+        // a -> c (sidechain), b -> c, c -> d, a -> d
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), c, 1.into(), true).unwrap();
+        graph.add_edge(c, 2.into(), d, 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), d, 1.into(), true).unwrap();
+
+        let delayed = |graph: &AudioGraphHelper| {
+            graph
+                .delay_compensations()
+                .map(|((src, _), (dst, _), delay)| (src, dst, delay))
+                .collect::<Vec<_>>()
+        };
+        graph.compile().unwrap();
+        assert_eq!(delayed(&graph), vec![(a, c, 4.0), (a, d, 4.0), (c, d, 4.0)]);
+
+        // The latency of `a` no longer reaches `d` through `c`, and the
+        // sidechain edge itself is not delayed.
+        graph.set_port_sidechain(c, 0.into(), true).unwrap();
+        assert!(graph.needs_compile());
+        graph.compile().unwrap();
+        assert_eq!(delayed(&graph), vec![(a, d, 4.0)]);
+    }
+
+    #[test]
+    fn compile_stream_matches_compile() {
+        let mut graph = AudioGraphHelper::new(1);
+        let a = graph.add_node(1.0);
+        let b = graph.add_node(0.0);
+        let c = graph.add_node(0.0);
+        graph.add_port(a, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(b, 0.into(), 0.into(), false).unwrap();
+        graph.add_port(c, 0.into(), 0.into(), true).unwrap();
+        graph.add_port(c, 1.into(), 0.into(), true).unwrap();
+        graph.add_edge(a, 0.into(), c, 0.into(), true).unwrap();
+        graph.add_edge(b, 0.into(), c, 1.into(), true).unwrap();
+
+        let schedule = graph.compile().unwrap();
+        assert_eq!(graph.schedule_delta(), &[a, b, c]);
+
+        graph.set_node_latency(b, 2.0).unwrap();
+        assert!(graph.needs_compile());
+        let mut streamed = vec![];
+        let num_buffers = graph.compile_stream(|entry| streamed.push(entry)).unwrap();
+        assert_eq!(num_buffers, schedule.num_buffers);
+        assert_eq!(
+            format!("{:?}", streamed),
+            format!("{:?}", graph.compile_up_to(c).unwrap().schedule)
+        );
+        assert!(!graph.needs_compile());
+        // The streamed schedule is not cached.
+        assert_eq!(graph.schedule_delta(), &[a, b, c]);
+        assert!(graph.compile_stream(|_| {}).is_ok());
+    }
+
     fn verify_scheduled_node(
         scheduled_node: &ScheduledNode,
         src_node: &Node,
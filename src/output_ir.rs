@@ -1,18 +1,28 @@
 //! Output data structures from the audio graph compiler.
 
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 
-use crate::input_ir::{Edge, NodeID, PortID, TypeIdx};
+use fnv::{FnvHashMap, FnvHashSet};
+
+use crate::error::RemapBuffersError;
+use crate::input_ir::{Edge, EdgeID, NodeID, PortID, TypeIdx};
 
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
 
 /// The index of the buffer.
+///
+/// Buffer indices are only unique within a single port type. To key on
+/// a buffer across all types, use a `(BufferIdx, TypeIdx)` pair.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BufferIdx(pub usize);
 
 /// A [CompiledSchedule] is the output of the graph compiler.
+///
+/// With the `serialize` feature, the schedule and every type in it can be
+/// serialized with serde, so it can be exported to JSON or any other
+/// format that serde supports, e.g. to compare against golden files.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
 pub struct CompiledSchedule {
@@ -21,11 +31,529 @@ pub struct CompiledSchedule {
     pub schedule: Vec<ScheduleEntry>,
     /// A list of delays that were inserted into the graph.
     pub delays: Vec<InsertedDelay>,
+    /// The buffer assigned to every constant output port, along with the
+    /// ID of the node it belongs to, in schedule order. These buffers are
+    /// not shared with any other port, so the engine only needs to fill
+    /// them once before processing starts.
+    pub constants: Vec<(NodeID, BufferAssignment)>,
+    /// The buffer assigned to every output port whose buffer is never
+    /// released, along with the ID of the node it belongs to, in schedule
+    /// order. These are the pinned and constant outputs and the sources of
+    /// weak edges, whose buffers are not reused by any other port.
+    pub persistent: Vec<(NodeID, BufferAssignment)>,
     /// The total number of buffers required to allocate, for
     /// each type of port.
     pub num_buffers: Vec<usize>,
 }
 
+impl CompiledSchedule {
+    /// Rewrite the index of every buffer in the schedule through `f`.
+    ///
+    /// `f` is called with the current index and type of each buffer, and
+    /// returns the new index for that buffer. The number of buffers for
+    /// each type is updated to fit the new indices.
+    ///
+    /// This will return an error if `f` maps two different buffers of the
+    /// same type to the same index. In this case the schedule has not been
+    /// modified.
+    pub fn remap_buffers(
+        &mut self,
+        f: impl Fn(BufferIdx, TypeIdx) -> BufferIdx,
+    ) -> Result<(), RemapBuffersError> {
+        let mut mapping: FnvHashMap<(BufferIdx, TypeIdx), BufferIdx> = FnvHashMap::default();
+        self.for_each_assignment_mut(|assignment| {
+            mapping
+                .entry((assignment.buffer_index, assignment.type_index))
+                .or_insert_with(|| f(assignment.buffer_index, assignment.type_index));
+        });
+
+        let mut used: FnvHashSet<(BufferIdx, TypeIdx)> = FnvHashSet::default();
+        for ((_, type_idx), new_buffer_index) in mapping.iter() {
+            if !used.insert((*new_buffer_index, *type_idx)) {
+                return Err(RemapBuffersError::NotInjective {
+                    type_idx: *type_idx,
+                    new_buffer_index: *new_buffer_index,
+                });
+            }
+        }
+
+        self.for_each_assignment_mut(|assignment| {
+            assignment.buffer_index = mapping[&(assignment.buffer_index, assignment.type_index)];
+        });
+
+        for num_buffers in self.num_buffers.iter_mut() {
+            *num_buffers = 0;
+        }
+        for (new_buffer_index, type_idx) in used.iter() {
+            let num_buffers = &mut self.num_buffers[type_idx.0];
+            *num_buffers = (*num_buffers).max(new_buffer_index.0 + 1);
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over the IDs of the nodes in the schedule, in the order they
+    /// are processed, skipping inserted delays and sums.
+    ///
+    /// This is cheaper than walking `schedule` when the caller has already
+    /// bound the buffers of each node and only needs the order.
+    pub fn node_ids(&self) -> impl Iterator<Item = NodeID> + '_ {
+        self.schedule.iter().filter_map(|entry| match entry {
+            ScheduleEntry::Node(node) => Some(node.id),
+            _ => None,
+        })
+    }
+
+    /// Find the index of the given node in `schedule`.
+    ///
+    /// Inserted delays and sums also occupy entries in the schedule, so
+    /// this is not the same as the number of nodes processed before it.
+    ///
+    /// This returns `None` if the node is not in the schedule.
+    pub fn schedule_position(&self, node_id: NodeID) -> Option<usize> {
+        self.schedule.iter().position(|entry| match entry {
+            ScheduleEntry::Node(node) => node.id == node_id,
+            _ => false,
+        })
+    }
+
+    /// List the buffer assigned to every output port of every node in the
+    /// schedule, along with the ID of the node it belongs to, in schedule
+    /// order.
+    ///
+    /// Since buffers are reused, the same buffer may appear more than once
+    /// with a different `generation`. Buffers written by inserted delays and
+    /// sums are not included.
+    pub fn buffer_producers(&self) -> Vec<(BufferAssignment, NodeID)> {
+        let mut producers = vec![];
+        for entry in self.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                for buffer in node.output_buffers.iter() {
+                    producers.push((*buffer, node.id));
+                }
+            }
+        }
+        producers
+    }
+
+    /// List the nodes whose buffer assignments or delay compensation differ
+    /// from those in `previous`, in schedule order.
+    ///
+    /// A node is listed if it is new, if one of its input or output buffer
+    /// assignments changed, or if a delay inserted on one of its incoming
+    /// edges was added, removed, or changed. All other nodes can keep the
+    /// bindings they had for `previous`.
+    pub fn changed_nodes(&self, previous: &CompiledSchedule) -> Vec<NodeID> {
+        let previous_nodes: FnvHashMap<NodeID, &ScheduledNode> = previous
+            .schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Node(node) => Some((node.id, node)),
+                _ => None,
+            })
+            .collect();
+
+        let mut changed: FnvHashSet<NodeID> = FnvHashSet::default();
+        let mut previous_delays: FnvHashMap<EdgeID, &InsertedDelay> = previous
+            .delays
+            .iter()
+            .map(|delay| (delay.edge.id, delay))
+            .collect();
+        for delay in self.delays.iter() {
+            match previous_delays.remove(&delay.edge.id) {
+                Some(previous_delay)
+                    if previous_delay.delay == delay.delay
+                        && previous_delay.input_buffer == delay.input_buffer
+                        && previous_delay.output_buffer == delay.output_buffer => {}
+                _ => {
+                    changed.insert(delay.edge.dst_node);
+                }
+            }
+        }
+        // Delays that are no longer needed.
+        changed.extend(previous_delays.values().map(|delay| delay.edge.dst_node));
+
+        let mut nodes = vec![];
+        for entry in self.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                let node_changed = match previous_nodes.get(&node.id) {
+                    Some(previous_node) => {
+                        previous_node.input_buffers != node.input_buffers
+                            || previous_node.output_buffers != node.output_buffers
+                    }
+                    None => true,
+                };
+                if node_changed || changed.contains(&node.id) {
+                    nodes.push(node.id);
+                }
+            }
+        }
+        nodes
+    }
+
+    /// List the input port of every node in the schedule that reads from
+    /// the given buffer, along with the ID of the node it belongs to, in
+    /// schedule order.
+    ///
+    /// This is the inverse of `CompiledSchedule::buffer_producers()`. Since
+    /// buffers are reused, the ports may read from different generations
+    /// of the buffer. Inserted delays and sums are not included.
+    pub fn buffer_consumers(
+        &self,
+        buffer_index: BufferIdx,
+        type_index: TypeIdx,
+    ) -> Vec<(NodeID, PortID)> {
+        let mut consumers = vec![];
+        for entry in self.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                for buffer in node.input_buffers.iter() {
+                    if buffer.buffer_index == buffer_index && buffer.type_index == type_index {
+                        consumers.push((node.id, buffer.port_id));
+                    }
+                }
+            }
+        }
+        consumers
+    }
+
+    /// Group the ports of every node in the schedule by the buffer they are
+    /// assigned, keeping only the buffers assigned to more than one port.
+    ///
+    /// The groups are sorted by type and then by index, and the ports in
+    /// each group are in schedule order, inputs before outputs. Since
+    /// buffers are reused, ports in the same group may use different
+    /// generations of the buffer. Inserted delays and sums are not included.
+    pub fn ports_sharing_buffer(&self) -> Vec<((BufferIdx, TypeIdx), Vec<(NodeID, PortID)>)> {
+        let mut groups: FnvHashMap<(BufferIdx, TypeIdx), Vec<(NodeID, PortID)>> =
+            FnvHashMap::default();
+        for entry in self.schedule.iter() {
+            if let ScheduleEntry::Node(node) = entry {
+                for buffer in node.input_buffers.iter().chain(node.output_buffers.iter()) {
+                    groups
+                        .entry((buffer.buffer_index, buffer.type_index))
+                        .or_default()
+                        .push((node.id, buffer.port_id));
+                }
+            }
+        }
+
+        let mut groups: Vec<_> = groups
+            .into_iter()
+            .filter(|(_, ports)| ports.len() > 1)
+            .collect();
+        groups.sort_unstable_by_key(|((idx, type_idx), _)| (type_idx.0, idx.0));
+        groups
+    }
+
+    /// List the buffers that are holding data right after the entry at
+    /// index `step` of `schedule` is processed, sorted by type and then by
+    /// index.
+    ///
+    /// A buffer holds data from the first entry that uses it until the last
+    /// entry that uses it with the same `generation`, so a buffer that is
+    /// only used within a single entry is not included. The buffers in
+    /// `persistent` are never released, so they are included from the
+    /// first entry that uses them to the end of the schedule, and constant
+    /// buffers are always included. This is the same as replaying the
+    /// acquire and release events of the buffer allocator (see
+    /// [BufferEvent]), except that the sources of weak edges are acquired
+    /// before the schedule starts.
+    pub fn live_buffers_after(&self, step: usize) -> Vec<(BufferIdx, TypeIdx)> {
+        // The first and last entry that use each generation of each buffer.
+        let mut lifetimes: FnvHashMap<(BufferIdx, TypeIdx, usize), (usize, usize)> =
+            FnvHashMap::default();
+        for (i, entry) in self.schedule.iter().enumerate() {
+            let mut record = |assignment: &BufferAssignment| {
+                let key = (
+                    assignment.buffer_index,
+                    assignment.type_index,
+                    assignment.generation,
+                );
+                let lifetime = lifetimes.entry(key).or_insert((i, i));
+                lifetime.1 = i;
+            };
+            match entry {
+                ScheduleEntry::Node(node) => {
+                    node.input_buffers.iter().for_each(&mut record);
+                    node.output_buffers.iter().for_each(&mut record);
+                }
+                ScheduleEntry::Delay(delay) => {
+                    record(&delay.input_buffer);
+                    record(&delay.output_buffer);
+                }
+                ScheduleEntry::Sum(sum) => {
+                    sum.input_buffers.iter().for_each(&mut record);
+                    record(&sum.output_buffer);
+                }
+            }
+        }
+
+        let mut live: FnvHashSet<(BufferIdx, TypeIdx)> = lifetimes
+            .iter()
+            .filter(|(_, (first, last))| *first <= step && step < *last)
+            .map(|((buffer_index, type_index, _), _)| (*buffer_index, *type_index))
+            .collect();
+        live.extend(
+            self.persistent
+                .iter()
+                .map(|(_, a)| (a.buffer_index, a.type_index, a.generation))
+                .filter(|key| matches!(lifetimes.get(key), Some((first, _)) if *first <= step))
+                .map(|(buffer_index, type_index, _)| (buffer_index, type_index)),
+        );
+        live.extend(
+            self.constants
+                .iter()
+                .map(|(_, assignment)| (assignment.buffer_index, assignment.type_index)),
+        );
+
+        let mut live = live.into_iter().collect::<Vec<_>>();
+        live.sort_unstable_by_key(|(buffer_index, type_index)| (type_index.0, buffer_index.0));
+        live
+    }
+
+    /// Convert the schedule into a flat list of operations, inserting a
+    /// copy before every node that reads a buffer that something else
+    /// also reads.
+    ///
+    /// After the copy, the node reads its own scratch buffer instead, so a
+    /// processor can safely write into its input buffers. A copy is also
+    /// inserted for every constant buffer a node reads, so that constant
+    /// buffers are never written to. Scratch buffers are only used by a
+    /// single node, so they are shared between nodes and added to the end
+    /// of the buffers of each type.
+    pub fn with_copies(&self) -> OpSchedule {
+        // The number of entries that read each generation of each buffer.
+        let mut readers: FnvHashMap<(BufferIdx, TypeIdx, usize), usize> = FnvHashMap::default();
+        let mut count = |assignment: &BufferAssignment| {
+            *readers
+                .entry((
+                    assignment.buffer_index,
+                    assignment.type_index,
+                    assignment.generation,
+                ))
+                .or_insert(0) += 1;
+        };
+        for entry in self.schedule.iter() {
+            match entry {
+                ScheduleEntry::Node(node) => node.input_buffers.iter().for_each(&mut count),
+                ScheduleEntry::Delay(delay) => count(&delay.input_buffer),
+                ScheduleEntry::Sum(sum) => sum.input_buffers.iter().for_each(&mut count),
+            }
+        }
+        let constants: FnvHashSet<(BufferIdx, TypeIdx)> = self
+            .constants
+            .iter()
+            .map(|(_, a)| (a.buffer_index, a.type_index))
+            .collect();
+
+        let mut num_buffers = self.num_buffers.clone();
+        let mut ops = Vec::with_capacity(self.schedule.len());
+        for entry in self.schedule.iter() {
+            let node = match entry {
+                ScheduleEntry::Node(node) => node,
+                _ => {
+                    ops.push(ScheduleOp::Entry(entry.clone()));
+                    continue;
+                }
+            };
+
+            let mut node = node.clone();
+            // The number of scratch buffers of each type used by this node.
+            let mut scratch = vec![0; num_buffers.len()];
+            for input in node.input_buffers.iter_mut() {
+                let key = (input.buffer_index, input.type_index, input.generation);
+                let shared = readers[&key] > 1;
+                let constant = constants.contains(&(input.buffer_index, input.type_index));
+                if !shared && !constant {
+                    continue;
+                }
+
+                let type_idx = input.type_index.0;
+                let copy = BufferAssignment {
+                    buffer_index: BufferIdx(self.num_buffers[type_idx] + scratch[type_idx]),
+                    generation: 0,
+                    should_clear: false,
+                    ..*input
+                };
+                scratch[type_idx] += 1;
+                num_buffers[type_idx] = num_buffers[type_idx].max(copy.buffer_index.0 + 1);
+
+                ops.push(ScheduleOp::Copy {
+                    src: *input,
+                    dst: copy,
+                });
+                *input = copy;
+            }
+            ops.push(ScheduleOp::Entry(ScheduleEntry::Node(node)));
+        }
+
+        OpSchedule { ops, num_buffers }
+    }
+
+    /// The index of the given buffer in a single array holding the buffers
+    /// of every type, with all buffers of type `0` first, then all buffers
+    /// of type `1`, and so on.
+    ///
+    /// The length of such an array is the sum of `num_buffers`.
+    pub fn flat_buffer_index(&self, assignment: &BufferAssignment) -> usize {
+        let offset: usize = self.num_buffers[..assignment.type_index.0].iter().sum();
+        offset + assignment.buffer_index.0
+    }
+
+    /// List the buffers of every node in the schedule, in schedule order,
+    /// as indices into a single array of buffers (see
+    /// `CompiledSchedule::flat_buffer_index()`).
+    ///
+    /// This is a flat layout for engines that bind each node's ports to
+    /// its buffers before processing starts. Inserted delays and sums are
+    /// not included.
+    pub fn binding_table(&self) -> Vec<NodeBinding> {
+        self.schedule
+            .iter()
+            .filter_map(|entry| match entry {
+                ScheduleEntry::Node(node) => Some(NodeBinding {
+                    node: node.id,
+                    inputs: node
+                        .input_buffers
+                        .iter()
+                        .map(|b| (self.flat_buffer_index(b), b.gain))
+                        .collect(),
+                    outputs: node
+                        .output_buffers
+                        .iter()
+                        .map(|b| self.flat_buffer_index(b))
+                        .collect(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Flatten the schedule into plain arrays of integers and floats, for
+    /// passing it to an engine that is not written in Rust.
+    ///
+    /// Every entry of the schedule is included, with its buffers given as
+    /// indices into a single array of buffers (see
+    /// `CompiledSchedule::flat_buffer_index()`).
+    pub fn to_flat_schedule(&self) -> FlatSchedule {
+        let mut flat = FlatSchedule {
+            num_buffers: self.num_buffers.iter().sum::<usize>() as u32,
+            ..FlatSchedule::default()
+        };
+        flat.input_offsets.push(0);
+        flat.output_offsets.push(0);
+
+        for entry in self.schedule.iter() {
+            match entry {
+                ScheduleEntry::Node(node) => {
+                    flat.kinds.push(FlatSchedule::NODE);
+                    flat.ids.push(node.id.0);
+                    for buffer in node.input_buffers.iter() {
+                        flat.inputs.push(self.flat_buffer_index(buffer) as u32);
+                        flat.input_gains.push(buffer.gain);
+                    }
+                    for buffer in node.output_buffers.iter() {
+                        flat.outputs.push(self.flat_buffer_index(buffer) as u32);
+                    }
+                }
+                ScheduleEntry::Delay(delay) => {
+                    flat.kinds.push(FlatSchedule::DELAY);
+                    flat.ids.push(delay.edge.id.0);
+                    flat.delays.push(delay.delay);
+                    flat.inputs
+                        .push(self.flat_buffer_index(&delay.input_buffer) as u32);
+                    flat.input_gains.push(delay.input_buffer.gain);
+                    flat.outputs
+                        .push(self.flat_buffer_index(&delay.output_buffer) as u32);
+                }
+                ScheduleEntry::Sum(sum) => {
+                    flat.kinds.push(FlatSchedule::SUM);
+                    flat.ids.push(u32::MAX);
+                    for buffer in sum.input_buffers.iter() {
+                        flat.inputs.push(self.flat_buffer_index(buffer) as u32);
+                        flat.input_gains.push(buffer.gain);
+                    }
+                    flat.outputs
+                        .push(self.flat_buffer_index(&sum.output_buffer) as u32);
+                }
+            }
+            flat.input_offsets.push(flat.inputs.len() as u32);
+            flat.output_offsets.push(flat.outputs.len() as u32);
+        }
+
+        flat
+    }
+
+    fn for_each_assignment_mut(&mut self, mut f: impl FnMut(&mut BufferAssignment)) {
+        for entry in self.schedule.iter_mut() {
+            match entry {
+                ScheduleEntry::Node(node) => {
+                    for assignment in node
+                        .input_buffers
+                        .iter_mut()
+                        .chain(node.output_buffers.iter_mut())
+                    {
+                        f(assignment);
+                    }
+                }
+                ScheduleEntry::Delay(delay) => {
+                    f(&mut delay.input_buffer);
+                    f(&mut delay.output_buffer);
+                }
+                ScheduleEntry::Sum(sum) => {
+                    for assignment in sum.input_buffers.iter_mut() {
+                        f(assignment);
+                    }
+                    f(&mut sum.output_buffer);
+                }
+            }
+        }
+        for delay in self.delays.iter_mut() {
+            f(&mut delay.input_buffer);
+            f(&mut delay.output_buffer);
+        }
+        for (_, assignment) in self.constants.iter_mut().chain(self.persistent.iter_mut()) {
+            f(assignment);
+        }
+    }
+}
+
+/// An [OpSchedule] is a schedule as a flat list of operations, as returned
+/// by `CompiledSchedule::with_copies()`.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct OpSchedule {
+    /// The operations to evaluate in order to render audio.
+    pub ops: Vec<ScheduleOp>,
+    /// The total number of buffers required to allocate, for each type of
+    /// port, including scratch buffers.
+    pub num_buffers: Vec<usize>,
+}
+
+/// A [ScheduleOp] is one operation of an [OpSchedule].
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub enum ScheduleOp {
+    /// An entry of the schedule to evaluate.
+    Entry(ScheduleEntry),
+    /// Copy the contents of the `src` buffer into the `dst` buffer.
+    Copy {
+        src: BufferAssignment,
+        dst: BufferAssignment,
+    },
+}
+
+/// A [BufferEvent] is a single step taken by the buffer allocator while
+/// compiling the graph.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferEvent {
+    /// The buffer was taken from the free list, or newly allocated.
+    Acquire(BufferIdx, TypeIdx),
+    /// The buffer was returned to the free list, so it can be reused.
+    Release(BufferIdx, TypeIdx),
+}
+
 /// A [ScheduleEntry] is one element of the schedule to evalute.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
@@ -48,12 +576,84 @@ pub struct ScheduledNode {
     pub id: NodeID,
     /// The latency of this node. Kept for debugging and visualization.
     pub latency: f64,
-    /// The assigned input buffers.
+    /// The assigned input buffers, in the same order as the input ports
+    /// of the [Node](crate::Node).
     pub input_buffers: Vec<BufferAssignment>,
-    /// The assigned output buffers.
+    /// The assigned output buffers, in the same order as the output ports
+    /// of the [Node](crate::Node).
     pub output_buffers: Vec<BufferAssignment>,
 }
 
+impl ScheduledNode {
+    /// Get the buffer assigned to the output port with the given ID.
+    ///
+    /// This returns `None` if the node has no output port with this ID.
+    pub fn output_buffer(&self, port_id: PortID) -> Option<BufferAssignment> {
+        self.output_buffers
+            .iter()
+            .find(|buffer| buffer.port_id == port_id)
+            .copied()
+    }
+}
+
+/// A [NodeBinding] lists the buffers of a single node in the schedule,
+/// as returned by `CompiledSchedule::binding_table()`.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct NodeBinding {
+    /// The unique ID of the node.
+    pub node: NodeID,
+    /// The flat index and gain of the buffer for each input port, in the
+    /// same order as `ScheduledNode::input_buffers`.
+    pub inputs: Vec<(usize, f32)>,
+    /// The flat index of the buffer for each output port, in the same
+    /// order as `ScheduledNode::output_buffers`.
+    pub outputs: Vec<usize>,
+}
+
+/// A [FlatSchedule] is a [CompiledSchedule] flattened into plain arrays,
+/// as returned by `CompiledSchedule::to_flat_schedule()`.
+///
+/// Entry `i` of the schedule reads the buffers
+/// `inputs[input_offsets[i]..input_offsets[i + 1]]` and writes the buffers
+/// `outputs[output_offsets[i]..output_offsets[i + 1]]`.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlatSchedule {
+    /// The kind of each entry, one of `FlatSchedule::NODE`,
+    /// `FlatSchedule::DELAY` or `FlatSchedule::SUM`.
+    pub kinds: Vec<u32>,
+    /// The ID of the node for each node entry, the ID of the edge for each
+    /// delay entry, and `u32::MAX` for each sum entry.
+    pub ids: Vec<u32>,
+    /// The start of the inputs of each entry, followed by the total number
+    /// of inputs.
+    pub input_offsets: Vec<u32>,
+    /// The flat index of every input buffer.
+    pub inputs: Vec<u32>,
+    /// The gain to apply to every input buffer, in the same order as
+    /// `inputs`.
+    pub input_gains: Vec<f32>,
+    /// The start of the outputs of each entry, followed by the total number
+    /// of outputs.
+    pub output_offsets: Vec<u32>,
+    /// The flat index of every output buffer.
+    pub outputs: Vec<u32>,
+    /// The amount of delay of every delay entry, in schedule order.
+    pub delays: Vec<f64>,
+    /// The total number of buffers to allocate, across all port types.
+    pub num_buffers: u32,
+}
+
+impl FlatSchedule {
+    /// The entry processes a node.
+    pub const NODE: u32 = 0;
+    /// The entry delays its input into its output.
+    pub const DELAY: u32 = 1;
+    /// The entry sums its inputs into its output.
+    pub const SUM: u32 = 2;
+}
+
 /// An [InsertedDelay] represents a required delay node to be inserted
 /// along some edge in order to compensate for different latencies along
 /// paths of the graph.
@@ -70,6 +670,29 @@ pub struct InsertedDelay {
     pub output_buffer: BufferAssignment,
 }
 
+impl InsertedDelay {
+    /// Split the delay into a number of whole blocks of `block_size`
+    /// samples plus the remaining delay within a single block.
+    ///
+    /// This is useful for engines that implement delays as a ring buffer
+    /// of whole blocks followed by a short sub-block delay. See also
+    /// `AudioGraphHelper::compile_with_block_size()`.
+    ///
+    /// This returns `None` if `block_size` is `0`, or if the delay is
+    /// negative or not finite.
+    pub fn split_at_block_size(&self, block_size: u64) -> Option<(u64, f64)> {
+        if block_size == 0 || !self.delay.is_finite() || self.delay < 0.0 {
+            return None;
+        }
+
+        let block_size = block_size as f64;
+        let whole_blocks = (self.delay / block_size).floor();
+        let remainder = self.delay - (whole_blocks * block_size);
+
+        Some((whole_blocks as u64, remainder))
+    }
+}
+
 /// An [InsertedSum] represents a point where multiple edges need to be merged
 /// into a single buffer, in order to support multiple inputs into the same
 /// port.
@@ -84,6 +707,11 @@ pub struct InsertedSum {
 
 /// A [Buffer Assignment] represents a single buffer assigned to an input
 /// or output port.
+///
+/// Equality and hashing are over every field (the gain by its bit
+/// pattern), so two assignments of the same buffer to different ports
+/// are not equal. To key on the buffer itself, use the
+/// `(buffer_index, type_index)` pair.
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub struct BufferAssignment {
@@ -100,6 +728,33 @@ pub struct BufferAssignment {
     /// how many times this buffer has been used before
     /// this assignment. Kept for debugging and visualization.
     pub generation: usize,
+    /// The gain to apply when reading this buffer, taken from the edge
+    /// that feeds it. This is `1.0` for output buffers and for inputs
+    /// that are not connected.
+    pub gain: f32,
+}
+
+impl PartialEq for BufferAssignment {
+    fn eq(&self, other: &Self) -> bool {
+        self.buffer_index == other.buffer_index
+            && self.type_index == other.type_index
+            && self.should_clear == other.should_clear
+            && self.port_id == other.port_id
+            && self.generation == other.generation
+            && self.gain.to_bits() == other.gain.to_bits()
+    }
+}
+impl Eq for BufferAssignment {}
+
+impl Hash for BufferAssignment {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.buffer_index.hash(state);
+        self.type_index.hash(state);
+        self.should_clear.hash(state);
+        self.port_id.hash(state);
+        self.generation.hash(state);
+        self.gain.to_bits().hash(state);
+    }
 }
 
 impl From<usize> for BufferIdx {